@@ -0,0 +1,243 @@
+use crate::CLIENT_UPDATE_INTERVAL;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query,
+    },
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use deducers::backend::{
+    add_chat_message, connect_player, disconnect_player,
+    error::GameError,
+    get_finished_game, get_lobby_info, get_scoreboard, get_state,
+    items::{buy_hint, player_guess_item, quizmaster_change_answer, quizmaster_reject, quizmaster_submit, unmask_question},
+    join_team, kick_player, pause_lobby,
+    protocol::{ClientCommand, LobbyView, ServerEvent, PROTOCOL_VERSION},
+    question_queue::{bid_question, convert_score, edit_question, submit_question, unvote_question, vote_question, withdraw_question},
+    reconnect_player, rematch_lobby, resume_lobby, spectate_lobby, start_lobby, transfer_key_player, unban_player, FinishedGame, LobbyInfo,
+    PlayerMessage, ScoreboardEntry,
+};
+use futures::{stream::SplitSink, SinkExt, StreamExt};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::interval;
+
+// A connected player on the JSON game socket, and the last lobby view they were sent
+struct Session {
+    lobby_id: String,
+    player_name: String,
+    last_view: Option<LobbyView>,
+}
+
+pub async fn game_socket(ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(handle_game_socket)
+}
+
+async fn handle_game_socket(socket: WebSocket) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut session: Option<Session> = None;
+    let mut update_interval = interval(Duration::from_secs_f64(CLIENT_UPDATE_INTERVAL));
+
+    if send_event(&mut sender, &ServerEvent::Hello { version: PROTOCOL_VERSION })
+        .await
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let events = tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => handle_command(&text, &mut session).await,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            _ = update_interval.tick() => poll_session(&mut session),
+        };
+        for event in events {
+            if send_event(&mut sender, &event).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn send_event(sender: &mut SplitSink<WebSocket, Message>, event: &ServerEvent) -> Result<(), axum::Error> {
+    let event_str = serde_json::to_string(event).expect("Server events should always serialize");
+    sender.send(Message::Text(event_str)).await
+}
+
+async fn handle_command(text: &str, session: &mut Option<Session>) -> Vec<ServerEvent> {
+    let command = match serde_json::from_str::<ClientCommand>(text) {
+        Ok(command) => command,
+        Err(e) => return vec![ServerEvent::InvalidCommand { reason: e.to_string() }],
+    };
+
+    if matches!(command, ClientCommand::Connect { .. } | ClientCommand::Reconnect { .. }) {
+        if session.is_some() {
+            return vec![ServerEvent::InvalidCommand {
+                reason: "Already connected to a lobby".to_owned(),
+            }];
+        }
+        return start_session(command, session);
+    }
+
+    let Some(Session { lobby_id, player_name, .. }) = session.as_ref() else {
+        return vec![ServerEvent::InvalidCommand {
+            reason: "Not connected to a lobby".to_owned(),
+        }];
+    };
+    // Most actions report failures through the players messages, which arrive with the next update
+    match command {
+        ClientCommand::Connect { .. } | ClientCommand::Reconnect { .. } => unreachable!("Connect commands are handled above"),
+        ClientCommand::Disconnect => {
+            disconnect_player(lobby_id, player_name);
+            *session = None;
+            return vec![ServerEvent::Disconnected];
+        }
+        ClientCommand::StartLobby => start_lobby(lobby_id, player_name),
+        ClientCommand::PauseLobby => pause_lobby(lobby_id, player_name),
+        ClientCommand::ResumeLobby => resume_lobby(lobby_id, player_name),
+        ClientCommand::Rematch => rematch_lobby(lobby_id, player_name),
+        ClientCommand::TransferKeyPlayer { new_key_player } => transfer_key_player(lobby_id, player_name, &new_key_player),
+        ClientCommand::KickPlayer {
+            player_name: player_to_kick,
+            reason,
+            ban_length,
+        } => kick_player(lobby_id, player_name, &player_to_kick, &reason, ban_length),
+        ClientCommand::UnbanPlayer {
+            player_name: player_to_unban,
+        } => unban_player(lobby_id, player_name, &player_to_unban),
+        ClientCommand::JoinTeam { team } => join_team(lobby_id, player_name, team),
+        ClientCommand::Chat { message, team_only } => add_chat_message(lobby_id, player_name, &message, team_only),
+        ClientCommand::SubmitQuestion { question, masked } => {
+            if let Err(e) = submit_question(lobby_id, player_name, question, masked).await {
+                return vec![ServerEvent::Error(e)];
+            }
+        }
+        ClientCommand::EditQuestion { question_id, question } => {
+            if let Err(e) = edit_question(lobby_id, player_name, question_id, question).await {
+                return vec![ServerEvent::Error(e)];
+            }
+        }
+        ClientCommand::WithdrawQuestion { question_id } => withdraw_question(lobby_id, player_name, question_id),
+        ClientCommand::VoteQuestion { question_id } => vote_question(lobby_id, player_name, question_id),
+        ClientCommand::UnvoteQuestion { question_id } => unvote_question(lobby_id, player_name, question_id),
+        ClientCommand::BidQuestion { question_id, amount } => bid_question(lobby_id, player_name, question_id, amount),
+        ClientCommand::GuessItem { item_id, guess } => player_guess_item(lobby_id, player_name, item_id, &guess),
+        ClientCommand::BuyHint { item_id, kind } => buy_hint(lobby_id, player_name, item_id, kind),
+        ClientCommand::UnmaskQuestion { question_id } => unmask_question(lobby_id, player_name, question_id),
+        ClientCommand::ConvertScore => convert_score(lobby_id, player_name),
+        ClientCommand::QuizmasterChangeAnswer {
+            question_id,
+            item_id,
+            answer,
+        } => quizmaster_change_answer(lobby_id, player_name, question_id, item_id, answer),
+        ClientCommand::QuizmasterSubmit { question_id } => quizmaster_submit(lobby_id, player_name, question_id),
+        ClientCommand::QuizmasterReject { question_id } => quizmaster_reject(lobby_id, player_name, question_id),
+    }
+    Vec::new()
+}
+
+fn start_session(command: ClientCommand, session: &mut Option<Session>) -> Vec<ServerEvent> {
+    let (lobby_id, player_name, result) = match command {
+        ClientCommand::Connect {
+            lobby_id,
+            player_name,
+            credential,
+            previous_session_token,
+            spectator,
+        } => {
+            let join = if spectator { spectate_lobby } else { connect_player };
+            let result = join(&lobby_id, &player_name, &credential, &previous_session_token);
+            (lobby_id, player_name, result)
+        }
+        ClientCommand::Reconnect {
+            lobby_id,
+            player_name,
+            session_token,
+        } => {
+            let result = reconnect_player(&lobby_id, &player_name, &session_token).map(|()| session_token);
+            (lobby_id, player_name, result)
+        }
+        _ => unreachable!("Only connect commands start a session"),
+    };
+    match result {
+        Ok(session_token) => {
+            *session = Some(Session {
+                lobby_id: lobby_id.clone(),
+                player_name: player_name.clone(),
+                last_view: None,
+            });
+            vec![ServerEvent::Connected {
+                lobby_id,
+                player_name,
+                session_token,
+            }]
+        }
+        Err(e) => vec![ServerEvent::Error(e)],
+    }
+}
+
+fn poll_session(session: &mut Option<Session>) -> Vec<ServerEvent> {
+    let Some(current) = session.as_mut() else {
+        return Vec::new();
+    };
+    let Ok((lobby, messages)) = get_state(&current.lobby_id, &current.player_name) else {
+        *session = None;
+        return vec![ServerEvent::Disconnected];
+    };
+
+    let kicked = messages.iter().any(|message| matches!(message, PlayerMessage::PlayerKicked(_)));
+    let mut events = messages.into_iter().map(ServerEvent::Message).collect::<Vec<_>>();
+
+    let view = LobbyView::new(&lobby, &current.player_name);
+    match &current.last_view {
+        None => events.push(ServerEvent::State(Box::new(view.clone()))),
+        Some(last_view) => {
+            if let Some(diff) = view.diff(last_view) {
+                events.push(ServerEvent::StateDiff(Box::new(diff)));
+            }
+        }
+    }
+    current.last_view = Some(view);
+
+    if kicked {
+        *session = None;
+        events.push(ServerEvent::Disconnected);
+    }
+    events
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<GameError>)>;
+
+const fn lookup_error(error: GameError) -> (StatusCode, Json<GameError>) {
+    let status = match error {
+        GameError::InvalidCredential => StatusCode::FORBIDDEN,
+        GameError::GameNotEnded => StatusCode::CONFLICT,
+        _ => StatusCode::NOT_FOUND,
+    };
+    (status, Json(error))
+}
+
+// Private lobbies need their password or invite code passed as `?credential=`
+#[derive(Deserialize)]
+pub struct CredentialQuery {
+    #[serde(default)]
+    credential: String,
+}
+
+pub async fn list_lobbies() -> Json<Vec<LobbyInfo>> {
+    let mut lobbies = get_lobby_info();
+    lobbies.sort_by(|a, b| a.id.cmp(&b.id));
+    Json(lobbies)
+}
+
+pub async fn lobby_scoreboard(Path(lobby_id): Path<String>, Query(query): Query<CredentialQuery>) -> ApiResult<Vec<ScoreboardEntry>> {
+    get_scoreboard(&lobby_id, &query.credential).map(Json).map_err(lookup_error)
+}
+
+pub async fn lobby_results(Path(lobby_id): Path<String>, Query(query): Query<CredentialQuery>) -> ApiResult<FinishedGame> {
+    get_finished_game(&lobby_id, &query.credential).map(Json).map_err(lookup_error)
+}
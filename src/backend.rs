@@ -1,37 +1,58 @@
 use crate::{
     backend::{
-        items::{add_item_to_lobby, ask_top_question},
+        error::{Field, GameError, GameResult},
+        items::{add_item_to_lobby, ask_top_question, reveal_remaining_items, start_overtime},
+        question_queue::expire_queued_questions,
         words::topup_lobby_if_available,
     },
-    IDLE_KICK_TIME, ITEM_NAME_PATTERN, LOBBY_ID_PATTERN, MAX_CHAT_LENGTH, MAX_CHAT_MESSAGES, MAX_ITEM_NAME_LENGTH, MAX_LOBBY_ID_LENGTH,
-    MAX_LOBBY_ITEMS, MAX_PLAYER_NAME_LENGTH, PLAYER_NAME_PATTERN,
+    IDLE_KICK_TIME, ITEM_NAME_PATTERN, LOBBY_ID_PATTERN, LOBBY_PASSWORD_PATTERN, MAX_CHAT_LENGTH, MAX_CHAT_MESSAGES, MAX_FINISHED_GAMES,
+    MAX_ITEM_NAME_LENGTH, MAX_KICK_REASON_LENGTH, MAX_LOBBY_ID_LENGTH, MAX_LOBBY_ITEMS, MAX_LOBBY_PASSWORD_LENGTH, MAX_LOBBY_PLAYERS,
+    MAX_PLAYER_NAME_LENGTH, MAX_QUESTIONS_PER_ITEM, MAX_SERIES_ROUNDS, MAX_TEAMS, MAX_TEAM_NAME_LENGTH, PLAYER_NAME_PATTERN,
+    RECONNECT_GRACE_TIME,
 };
-use anyhow::{anyhow, bail, ensure, Result};
-use once_cell::sync::Lazy;
 use rand::prelude::*;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
-    time,
+    sync::{Arc, LazyLock, Mutex},
 };
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, EnumString};
 
+pub mod ai;
+pub mod clock;
+pub mod error;
 pub mod items;
 pub mod openai;
+pub mod protocol;
 pub mod question_queue;
+pub mod snapshot;
 pub mod words;
 
-#[derive(Clone, Default)]
+#[cfg(test)]
+mod testing;
+
+pub use clock::get_current_time;
+
+// Fields missing from older snapshots are filled in with their defaults
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Lobby {
     pub id: String,
     pub state: LobbyState,
+    pub round: usize,
     pub elapsed_time: f64,
+    pub overtime: bool,
     pub last_update: f64,
     pub key_player: String,
+    pub password: String,
+    pub invite_code: String,
 
     pub players: HashMap<String, Player>,
+    // Shared balance of each team when coins are pooled
+    pub team_coins: Vec<usize>,
+    pub bans: Vec<Ban>,
     pub chat_messages: Vec<ChatMessage>,
     pub questions_queue: Vec<QueuedQuestion>,
     pub quizmaster_queue: Vec<QueuedQuestion>,
@@ -40,36 +61,213 @@ pub struct Lobby {
     pub questions: Vec<Question>,
 
     pub settings: LobbySettings,
+    // Whether the game started with a quizmaster, the AI stands in when they leave but a rematch goes back to how it was set up
+    pub started_player_controlled: bool,
 
     pub coins_countdown: f64,
     pub questions_queue_countdown: f64,
     pub items_counter: usize,
     pub questions_counter: usize,
+    pub queued_questions_counter: usize,
 }
 
 impl Lobby {
+    // Game actions are only allowed while a round is being played
+    pub const fn ensure_playing(&self) -> GameResult<()> {
+        match self.state {
+            LobbyState::Play => Ok(()),
+            LobbyState::Paused => Err(GameError::GamePaused),
+            _ => Err(GameError::LobbyNotStarted),
+        }
+    }
+
     pub fn questions_queue_active(&self) -> bool {
         self.questions_queue
             .iter()
-            .any(|q| q.voters.len() >= self.settings.question_min_votes)
+            .any(|q| self.question_priority(q) >= self.question_threshold())
+    }
+
+    // Backing the top question needs before it is asked, votes when voting and total coins bid in an auction
+    pub const fn question_threshold(&self) -> usize {
+        match self.settings.queue_mode {
+            QueueMode::Voting => self.settings.question_min_votes,
+            QueueMode::Auction => self.settings.min_total_bid,
+        }
+    }
+
+    // How strongly a queued question is backed, its vote count or in an auction the total of its bids
+    pub fn question_priority(&self, question: &QueuedQuestion) -> usize {
+        match self.settings.queue_mode {
+            QueueMode::Voting => question.voters.len(),
+            QueueMode::Auction => question.bids.values().sum(),
+        }
+    }
+
+    // Seconds until the time limit runs out, or until the final guessing window closes once it has, None without a limit
+    pub fn time_left(&self) -> Option<f64> {
+        if self.settings.time_limit_minutes == 0 {
+            return None;
+        }
+        let mut deadline = self.settings.time_limit_minutes as f64 * 60.0;
+        if self.overtime {
+            deadline += self.settings.final_guess_seconds as f64;
+        }
+        Some((deadline - self.elapsed_time).max(0.0))
+    }
+
+    // The invite code always lets a player in, the password only counts once the key player has set one
+    pub fn accepts_credential(&self, credential: &str) -> bool {
+        match self.settings.visibility {
+            Visibility::Public | Visibility::Unlisted => true,
+            Visibility::Password => credential == self.invite_code || (!self.password.is_empty() && credential == self.password),
+        }
+    }
+
+    // Scoreboards and results of public lobbies are open to anyone, otherwise the password or invite code is needed
+    pub fn shares_results(&self, credential: &str) -> bool {
+        self.settings.visibility == Visibility::Public
+            || credential == self.invite_code
+            || (!self.password.is_empty() && credential == self.password)
+    }
+
+    // Teammates spend from one team balance when coins are pooled, everyone else has their own
+    fn pooled_team(&self, player_name: &str) -> Option<usize> {
+        if !(self.settings.team_mode && self.settings.pooled_coins) {
+            return None;
+        }
+        self.players.get(player_name).and_then(|player| player.team)
+    }
+
+    pub fn coins(&self, player_name: &str) -> usize {
+        if let Some(team) = self.pooled_team(player_name) {
+            return self.team_coins.get(team).copied().unwrap_or_default();
+        }
+        self.players.get(player_name).map_or(0, |player| player.coins)
+    }
+
+    pub fn coins_mut(&mut self, player_name: &str) -> Option<&mut usize> {
+        if let Some(team) = self.pooled_team(player_name) {
+            return self.team_coins.get_mut(team);
+        }
+        self.players.get_mut(player_name).map(|player| &mut player.coins)
+    }
+
+    // Seconds until a queued question expires, None if questions never expire
+    pub fn question_expires_in(&self, question: &QueuedQuestion) -> Option<f64> {
+        (self.settings.question_expiry_seconds > 0)
+            .then(|| (question.queued_at + self.settings.question_expiry_seconds as f64 - self.elapsed_time).max(0.0))
+    }
+
+    // Gives each voter back the coin they spent, a player who voted several times appears once per vote
+    pub fn refund_voters(&mut self, voters: &[String]) {
+        for voter in voters {
+            if let Some(coins) = self.coins_mut(voter) {
+                *coins += 1;
+            }
+        }
+    }
+
+    pub fn refund_bids(&mut self, bids: &HashMap<String, usize>) {
+        for (bidder, bid) in bids {
+            if let Some(coins) = self.coins_mut(bidder) {
+                *coins += bid;
+            }
+        }
+    }
+
+    // Everyone who backed a question that won't be asked gets their coins back
+    pub fn refund_backers(&mut self, question: &QueuedQuestion) {
+        self.refund_voters(&question.voters);
+        self.refund_bids(&question.bids);
+    }
+
+    // The combined score of each team's players, indexed like the team names
+    pub fn team_scores(&self) -> Vec<usize> {
+        (0..self.settings.teams.len())
+            .map(|team| self.players.values().filter(|p| p.team == Some(team)).map(|p| p.score).sum())
+            .collect()
+    }
+
+    fn smallest_team(&self) -> usize {
+        (0..self.settings.teams.len())
+            .min_by_key(|team| self.players.values().filter(|p| p.team == Some(*team)).count())
+            .unwrap_or_default()
+    }
+
+    // Team chat is only shown to that team, spectators and players without a team only see the open chat
+    pub fn visible_chat(&self, player_name: &str) -> Vec<ChatMessage> {
+        let team = self.players.get(player_name).and_then(|p| p.team);
+        self.chat_messages
+            .iter()
+            .filter(|message| message.team.is_none() || message.team == team)
+            .cloned()
+            .collect()
+    }
+
+    // Drops expired bans, then finds one matching the player name or their previous session
+    pub fn active_ban(&mut self, player_name: &str, session_token: &str) -> Option<&Ban> {
+        let current_time = get_current_time();
+        self.bans
+            .retain(|ban| ban.expires_at.is_none_or(|expires_at| expires_at > current_time));
+        self.bans
+            .iter()
+            .find(|ban| ban.player_name == player_name || (!session_token.is_empty() && ban.session_token == session_token))
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub player_name: String,
+    pub session_token: String,
+    pub reason: String,
+    pub expires_at: Option<f64>,
+}
+
+impl Ban {
+    pub fn to_error(&self) -> GameError {
+        GameError::Banned {
+            minutes_left: self
+                .expires_at
+                .map(|expires_at| ((expires_at - get_current_time()) / 60.0).ceil() as usize),
+        }
     }
 }
 
-#[derive(Clone, Default, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanLength {
+    #[default]
+    #[strum(serialize = "No ban")]
+    NoBan,
+    #[strum(serialize = "{0} minutes")]
+    Minutes(usize),
+    Permanent,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LobbyState {
     #[default]
     Open,
     Starting,
     Play,
+    Paused,
     Ended,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct LobbySettings {
     pub item_count: usize,
     pub difficulty: Difficulty,
     pub player_controlled: bool,
     pub theme: String,
+    pub visibility: Visibility,
+    pub max_players: usize,
+    pub rounds: usize,
+    pub team_mode: bool,
+    pub pooled_coins: bool,
+    pub teams: Vec<String>,
 
     pub starting_coins: usize,
     pub coin_every_x_seconds: usize,
@@ -80,6 +278,25 @@ pub struct LobbySettings {
     pub masked_question_cost: usize,
     pub guess_item_cost: usize,
     pub question_min_votes: usize,
+    pub questions_per_item: usize,
+    pub scoring_curve: ScoringCurve,
+    pub time_limit_minutes: usize,
+    pub final_guess_seconds: usize,
+    pub queue_mode: QueueMode,
+    pub min_total_bid: usize,
+    pub hint_cost: usize,
+    pub hint_score_penalty: usize,
+    pub public_hints: bool,
+    pub ai_clue_after_questions: usize,
+    pub unmask_cost: usize,
+    pub unmask_asker_percent: usize,
+    pub unmask_after_questions: usize,
+    pub informative_reward: usize,
+    pub informative_reward_kind: RewardKind,
+    pub reward_voters: bool,
+    pub withdraw_refund_percent: usize,
+    pub refund_unvotes: bool,
+    pub question_expiry_seconds: usize,
 
     pub score_to_coins_ratio: usize,
 }
@@ -91,6 +308,12 @@ impl Default for LobbySettings {
             difficulty: Difficulty::Easy,
             player_controlled: false,
             theme: String::new(),
+            visibility: Visibility::Public,
+            max_players: 16,
+            rounds: 1,
+            team_mode: false,
+            pooled_coins: false,
+            teams: vec!["Red".to_owned(), "Blue".to_owned()],
             starting_coins: 8,
             coin_every_x_seconds: 6,
             submit_question_every_x_seconds: 10,
@@ -99,17 +322,124 @@ impl Default for LobbySettings {
             masked_question_cost: 12,
             guess_item_cost: 6,
             question_min_votes: 3,
+            questions_per_item: 20,
+            scoring_curve: ScoringCurve::Linear,
+            time_limit_minutes: 0,
+            final_guess_seconds: 30,
+            queue_mode: QueueMode::Voting,
+            min_total_bid: 5,
+            hint_cost: 8,
+            hint_score_penalty: 2,
+            public_hints: true,
+            ai_clue_after_questions: 0,
+            unmask_cost: 10,
+            unmask_asker_percent: 50,
+            unmask_after_questions: 0,
+            informative_reward: 0,
+            informative_reward_kind: RewardKind::Coins,
+            reward_voters: false,
+            withdraw_refund_percent: 50,
+            refund_unvotes: true,
+            question_expiry_seconds: 0,
             score_to_coins_ratio: 4,
         }
     }
 }
 
+// Every advanced setting as (key, min, max), the range a key player can set it to
+pub const ADVANCED_SETTINGS: [(&str, usize, usize); 24] = [
+    ("starting_coins", 1, 1000),
+    ("coin_every_x_seconds", 1, 30),
+    ("submit_question_every_x_seconds", 1, 50),
+    ("add_item_every_x_questions", 1, 20),
+    ("submit_question_cost", 0, 100),
+    ("masked_question_cost", 0, 100),
+    ("guess_item_cost", 0, 100),
+    ("question_min_votes", 0, 20),
+    ("min_total_bid", 0, 100),
+    ("questions_per_item", 5, MAX_QUESTIONS_PER_ITEM),
+    ("score_to_coins_ratio", 0, 100),
+    ("max_players", 1, MAX_LOBBY_PLAYERS),
+    ("rounds", 1, MAX_SERIES_ROUNDS),
+    ("time_limit_minutes", 0, 60),
+    ("final_guess_seconds", 0, 120),
+    ("withdraw_refund_percent", 0, 100),
+    ("question_expiry_seconds", 0, 600),
+    ("hint_cost", 0, 100),
+    ("hint_score_penalty", 0, 20),
+    ("ai_clue_after_questions", 0, MAX_QUESTIONS_PER_ITEM),
+    ("unmask_cost", 0, 100),
+    ("unmask_asker_percent", 0, 100),
+    ("unmask_after_questions", 0, 50),
+    ("informative_reward", 0, 20),
+];
+
+impl LobbySettings {
+    pub fn advanced_mut(&mut self, key: &str) -> Option<&mut usize> {
+        Some(match key {
+            "starting_coins" => &mut self.starting_coins,
+            "coin_every_x_seconds" => &mut self.coin_every_x_seconds,
+            "submit_question_every_x_seconds" => &mut self.submit_question_every_x_seconds,
+            "add_item_every_x_questions" => &mut self.add_item_every_x_questions,
+            "submit_question_cost" => &mut self.submit_question_cost,
+            "masked_question_cost" => &mut self.masked_question_cost,
+            "guess_item_cost" => &mut self.guess_item_cost,
+            "question_min_votes" => &mut self.question_min_votes,
+            "min_total_bid" => &mut self.min_total_bid,
+            "questions_per_item" => &mut self.questions_per_item,
+            "score_to_coins_ratio" => &mut self.score_to_coins_ratio,
+            "max_players" => &mut self.max_players,
+            "rounds" => &mut self.rounds,
+            "time_limit_minutes" => &mut self.time_limit_minutes,
+            "final_guess_seconds" => &mut self.final_guess_seconds,
+            "withdraw_refund_percent" => &mut self.withdraw_refund_percent,
+            "question_expiry_seconds" => &mut self.question_expiry_seconds,
+            "hint_cost" => &mut self.hint_cost,
+            "hint_score_penalty" => &mut self.hint_score_penalty,
+            "ai_clue_after_questions" => &mut self.ai_clue_after_questions,
+            "unmask_cost" => &mut self.unmask_cost,
+            "unmask_asker_percent" => &mut self.unmask_asker_percent,
+            "unmask_after_questions" => &mut self.unmask_after_questions,
+            "informative_reward" => &mut self.informative_reward,
+            _ => return None,
+        })
+    }
+
+    // Points for guessing an item correctly after it has been asked about `answers` times
+    pub fn guess_score(&self, answers: usize) -> usize {
+        let max_score = self.questions_per_item;
+        let remaining = max_score.saturating_sub(answers);
+        match self.scoring_curve {
+            ScoringCurve::Linear => remaining,
+            // Halves every quarter of the item's questions, so early guesses are worth far more
+            ScoringCurve::Exponential => (max_score as f64 * 0.5_f64.powf(answers as f64 * 4.0 / max_score as f64)).round() as usize,
+            ScoringCurve::Flat => max_score / 2,
+            ScoringCurve::DifficultyWeighted => match self.difficulty {
+                Difficulty::Easy => remaining,
+                Difficulty::Medium => remaining * 3 / 2,
+                Difficulty::Hard => remaining * 2,
+            },
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum AlterLobbySetting {
     ItemCount(usize),
     Difficulty(Difficulty),
     PlayerControlled(bool),
     Theme(String),
+    Visibility(Visibility),
+    Password(String),
+    ScoringCurve(ScoringCurve),
+    QueueMode(QueueMode),
+    PublicHints(bool),
+    InformativeRewardKind(RewardKind),
+    RewardVoters(bool),
+    RefundUnvotes(bool),
+    TeamMode(bool),
+    PooledCoins(bool),
+    Teams(Vec<String>),
     AddItem(String),
     RemoveItem(String),
     RefreshItem(String),
@@ -117,88 +447,201 @@ pub enum AlterLobbySetting {
     Advanced(String, usize),
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
 pub enum Difficulty {
     Easy,
     Medium,
     Hard,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
+pub enum ScoringCurve {
+    Linear,
+    Exponential,
+    Flat,
+    #[strum(serialize = "Difficulty Weighted")]
+    DifficultyWeighted,
+}
+
+// Voting asks the question with the most 1 coin votes next, an auction asks the one with the highest total of sealed bids
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
+pub enum QueueMode {
+    Voting,
+    Auction,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
+pub enum RewardKind {
+    Coins,
+    Score,
+}
+
+// Public lobbies are listed in the lobby browser, unlisted ones are joined by id and password ones need the password or invite code
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
+pub enum Visibility {
+    Public,
+    Unlisted,
+    Password,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum PlayerMessage {
     ItemAdded,
     QuestionAsked,
     QuestionRejected(String),
-    AlertPopup(String),
+    AlertPopup(GameError),
     GameStart,
     CoinGiven,
     ItemGuessed(String, usize, String),
     GuessIncorrect,
     ItemRemoved(usize, String),
+    RoundOver(String),
+    TimeUp(String),
     Winner(String),
-    PlayerKicked,
+    PlayerKicked(String),
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Player {
     pub name: String,
     pub last_contact: f64,
+    pub joined_at: f64,
     pub quizmaster: bool,
+    pub spectator: bool,
+    pub team: Option<usize>,
     pub score: usize,
+    pub round_scores: Vec<usize>,
+    // Points earned in the current round, converting score to coins later doesn't take them back
+    pub round_score: usize,
     pub coins: usize,
+    pub connected: bool,
+    pub session_token: String,
+    #[serde(skip)]
     pub messages: Vec<PlayerMessage>,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize)]
 pub struct PlayerReduced {
     pub name: String,
+    pub connected: bool,
     pub quizmaster: bool,
+    pub spectator: bool,
+    pub team: Option<usize>,
     pub score: usize,
-    pub coins: usize,
+    pub round_scores: Vec<usize>,
+    pub coins: Option<usize>,
 }
 
 impl Player {
-    pub fn reduce(&self) -> PlayerReduced {
+    pub const fn award_points(&mut self, points: usize) {
+        self.score += points;
+        self.round_score += points;
+    }
+
+    // Bids are sealed in an auction, so only the player and teammates sharing their balance see how many coins they have left
+    pub fn reduce(&self, lobby: &Lobby, viewer: &str) -> PlayerReduced {
+        let shares_balance = self.name == viewer
+            || lobby
+                .pooled_team(viewer)
+                .is_some_and(|team| lobby.pooled_team(&self.name) == Some(team));
+        let coins_visible = lobby.settings.queue_mode != QueueMode::Auction || shares_balance;
         PlayerReduced {
             name: self.name.clone(),
+            connected: self.connected,
             quizmaster: self.quizmaster,
+            spectator: self.spectator,
+            team: self.team,
             score: self.score,
-            coins: self.coins,
+            round_scores: self.round_scores.clone(),
+            coins: coins_visible.then(|| lobby.coins(&self.name)),
         }
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub player: String,
     pub message: String,
+    #[serde(default)]
+    pub team: Option<usize>,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct QueuedQuestion {
+    #[serde(default)]
+    pub id: usize,
     pub player: String,
     pub question: String,
     pub masked: bool,
     pub voters: Vec<String>,
+    // Sealed bids by player name, only used when questions are auctioned
+    #[serde(default)]
+    pub bids: HashMap<String, usize>,
     pub answers: HashMap<usize, Answer>,
+    // Game time when the question was queued, so paused time doesn't count towards expiry
+    #[serde(default)]
+    pub queued_at: f64,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Item {
     pub name: String,
     pub id: usize,
     pub answers: HashMap<usize, Answer>,
+    #[serde(default)]
+    pub hints: Vec<Hint>,
+    // Vague clue from the AI once the item has stalled, shown to everyone
+    #[serde(default)]
+    pub clue: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hint {
+    pub kind: HintKind,
+    pub text: String,
+    pub purchaser: String,
+    pub public: bool,
+    // Index of the revealed character for letter hints
+    pub position: Option<usize>,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+impl Hint {
+    pub fn visible_to(&self, player_name: &str, is_quizmaster: bool) -> bool {
+        self.public || is_quizmaster || self.purchaser == player_name
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, EnumString, Display, EnumIter, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HintKind {
+    #[strum(serialize = "Word Length")]
+    Length,
+    #[strum(serialize = "First Letter")]
+    FirstLetter,
+    #[strum(serialize = "Reveal Letter")]
+    Letter,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Question {
     pub player: String,
     pub id: usize,
     pub text: String,
     pub masked: bool,
+    // Players who paid to see the text of a masked question
+    #[serde(default)]
+    pub unmasked_by: Vec<String>,
+}
+
+impl Question {
+    pub fn visible_to(&self, player_name: &str, is_quizmaster: bool) -> bool {
+        !self.masked || is_quizmaster || self.player == player_name || self.unmasked_by.iter().any(|name| name == player_name)
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, EnumString, Display, EnumIter)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, EnumString, Display, EnumIter, Serialize, Deserialize)]
 pub enum Answer {
     Yes,
     No,
@@ -217,54 +660,166 @@ impl Answer {
     }
 }
 
-static LOBBYS: Lazy<Arc<Mutex<HashMap<String, Lobby>>>> = Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
+static LOBBYS: LazyLock<Arc<Mutex<HashMap<String, Lobby>>>> = LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
 
-pub fn with_lobby<F, T>(lobby_id: &str, f: F) -> Result<T>
+pub fn with_lobby<F, T>(lobby_id: &str, f: F) -> GameResult<T>
 where
-    F: FnOnce(&mut Lobby) -> Result<T>,
+    F: FnOnce(&mut Lobby) -> GameResult<T>,
 {
     let mut lobbys_lock = LOBBYS.lock().unwrap();
-    let lobby = lobbys_lock
-        .get_mut(lobby_id)
-        .ok_or_else(|| anyhow!("Lobby '{lobby_id}' not found"))?;
+    let lobby = lobbys_lock.get_mut(lobby_id).ok_or_else(|| GameError::LobbyNotFound {
+        lobby_id: lobby_id.to_owned(),
+    })?;
     f(lobby)
 }
 
-pub fn with_player<F, T>(lobby_id: &str, player_name: &str, f: F) -> Result<T>
+pub fn with_player<F, T>(lobby_id: &str, player_name: &str, f: F) -> GameResult<T>
 where
-    F: FnOnce(Lobby, &mut Player) -> Result<T>,
+    F: FnOnce(Lobby, &mut Player) -> GameResult<T>,
 {
     with_lobby(lobby_id, |lobby| {
         let lobby_state = lobby.clone();
-        let player = lobby
-            .players
-            .get_mut(player_name)
-            .ok_or_else(|| anyhow!("Player '{player_name}' not found"))?;
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
         f(lobby_state, player)
     })
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Serialize)]
 pub struct LobbyInfo {
     pub id: String,
+    pub state: LobbyState,
     pub started: bool,
     pub players_count: usize,
+    pub spectators_count: usize,
+    pub theme: String,
+    pub difficulty: Difficulty,
+    pub password_protected: bool,
 }
 
 pub fn get_lobby_info() -> Vec<LobbyInfo> {
     let lobbys_lock = LOBBYS.lock().unwrap();
     let mut lobby_infos = Vec::new();
     for (id, lobby) in &lobbys_lock.clone() {
+        if lobby.settings.visibility == Visibility::Unlisted {
+            continue;
+        }
         lobby_infos.push(LobbyInfo {
             id: id.clone(),
-            started: matches!(lobby.state, LobbyState::Play | LobbyState::Starting),
-            players_count: lobby.players.len(),
+            state: lobby.state.clone(),
+            started: matches!(lobby.state, LobbyState::Play | LobbyState::Paused | LobbyState::Starting),
+            players_count: lobby.players.values().filter(|player| !player.spectator).count(),
+            spectators_count: lobby.players.values().filter(|player| player.spectator).count(),
+            theme: lobby.settings.theme.clone(),
+            difficulty: lobby.settings.difficulty,
+            password_protected: lobby.settings.visibility == Visibility::Password,
         });
     }
     lobby_infos
 }
 
-pub fn create_lobby(lobby_id: &str, player_name: &str) -> Result<()> {
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreboardEntry {
+    pub name: String,
+    pub score: usize,
+    pub round_scores: Vec<usize>,
+    pub quizmaster: bool,
+}
+
+fn scoreboard(lobby: &Lobby) -> Vec<ScoreboardEntry> {
+    let mut scoreboard = lobby
+        .players
+        .values()
+        .filter(|player| !player.spectator)
+        .map(|player| ScoreboardEntry {
+            name: player.name.clone(),
+            score: player.score,
+            round_scores: player.round_scores.clone(),
+            quizmaster: player.quizmaster,
+        })
+        .collect::<Vec<_>>();
+    scoreboard.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    scoreboard
+}
+
+pub fn get_scoreboard(lobby_id: &str, credential: &str) -> GameResult<Vec<ScoreboardEntry>> {
+    with_lobby(lobby_id, |lobby| {
+        if !lobby.shares_results(credential) {
+            return Err(GameError::InvalidCredential);
+        }
+        Ok(scoreboard(lobby))
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FinishedGame {
+    pub lobby_id: String,
+    pub ended_at: f64,
+    pub winners: Vec<String>,
+    pub scoreboard: Vec<ScoreboardEntry>,
+    pub questions_asked: usize,
+    pub items_count: usize,
+}
+
+// Who may read a finished game's results, kept from the lobby so private results stay private after it is removed
+#[derive(Clone, Serialize, Deserialize)]
+struct FinishedGameRecord {
+    game: FinishedGame,
+    public: bool,
+    credentials: Vec<String>,
+}
+
+// Results of the most recently finished games, kept after their lobbies are removed and saved in snapshots
+static FINISHED_GAMES: LazyLock<Mutex<Vec<FinishedGameRecord>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+pub fn record_finished_game(lobby: &Lobby, winners: Vec<String>) {
+    let mut finished_games = FINISHED_GAMES.lock().unwrap();
+    finished_games.retain(|record| record.game.lobby_id != lobby.id);
+    finished_games.push(FinishedGameRecord {
+        game: FinishedGame {
+            lobby_id: lobby.id.clone(),
+            ended_at: get_current_time(),
+            winners,
+            scoreboard: scoreboard(lobby),
+            questions_asked: lobby.questions_counter,
+            items_count: lobby.items_counter,
+        },
+        public: lobby.settings.visibility == Visibility::Public,
+        credentials: [&lobby.invite_code, &lobby.password]
+            .into_iter()
+            .filter(|credential| !credential.is_empty())
+            .cloned()
+            .collect(),
+    });
+    if finished_games.len() > MAX_FINISHED_GAMES {
+        finished_games.remove(0);
+    }
+}
+
+pub fn get_finished_game(lobby_id: &str, credential: &str) -> GameResult<FinishedGame> {
+    let record = FINISHED_GAMES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|record| record.game.lobby_id == lobby_id)
+        .cloned();
+    // A lobby that is still playing has no results yet, which clients need to tell apart from no lobby at all
+    let Some(record) = record else {
+        let shares_results = with_lobby(lobby_id, |lobby| Ok(lobby.shares_results(credential)))?;
+        return Err(if shares_results {
+            GameError::GameNotEnded
+        } else {
+            GameError::InvalidCredential
+        });
+    };
+    if !record.public && !record.credentials.iter().any(|accepted| accepted == credential) {
+        return Err(GameError::InvalidCredential);
+    }
+    Ok(record.game)
+}
+
+pub fn create_lobby(lobby_id: &str, player_name: &str) -> GameResult<()> {
     let mut lobbys_lock = LOBBYS.lock().unwrap();
     if lobbys_lock.contains_key(lobby_id) {
         return Ok(());
@@ -275,6 +830,7 @@ pub fn create_lobby(lobby_id: &str, player_name: &str) -> Result<()> {
             id: lobby_id.to_owned(),
             last_update: get_current_time(),
             key_player: player_name.to_owned(),
+            invite_code: random_code(8),
             ..Default::default()
         },
     );
@@ -287,7 +843,7 @@ pub fn create_lobby(lobby_id: &str, player_name: &str) -> Result<()> {
     Ok(())
 }
 
-fn create_debug_lobby(lobby_id: &str) -> Result<()> {
+fn create_debug_lobby(lobby_id: &str) -> GameResult<()> {
     println!("Creating debug lobby");
     with_lobby(lobby_id, |lobby| {
         lobby.state = LobbyState::Play;
@@ -302,19 +858,24 @@ fn create_debug_lobby(lobby_id: &str) -> Result<()> {
             lobby.chat_messages.push(ChatMessage {
                 player: "debug".to_owned(),
                 message: rand::random::<usize>().to_string(),
+                team: None,
             });
         }
         let questions = vec!["brown", "red", "yellow", "green", "blue", "purple", "orange", "black", "white"];
         for question in questions {
             let question = format!("Is it {question}");
+            lobby.queued_questions_counter += 1;
             lobby.questions_queue.push(QueuedQuestion {
+                id: lobby.queued_questions_counter,
                 player: "debug".to_owned(),
                 question: question.clone(),
                 voters: (0..rand::random::<usize>() % 6)
                     .map(|_| rand::random::<usize>().to_string())
                     .collect(),
-                masked: rand::random::<usize>() % 5 == 0,
+                masked: rand::random::<usize>().is_multiple_of(5),
+                bids: HashMap::new(),
                 answers: HashMap::new(),
+                queued_at: 0.0,
             });
             let id = lobby.questions_counter;
             lobby.questions_counter += 1;
@@ -322,7 +883,8 @@ fn create_debug_lobby(lobby_id: &str) -> Result<()> {
                 player: "debug".to_owned(),
                 id,
                 text: question.clone(),
-                masked: rand::random::<usize>() % 5 == 0,
+                masked: rand::random::<usize>().is_multiple_of(5),
+                unmasked_by: Vec::new(),
             });
             for item in &mut lobby.items {
                 item.answers.insert(id, Answer::iter().choose(&mut rand::thread_rng()).unwrap());
@@ -335,29 +897,84 @@ fn create_debug_lobby(lobby_id: &str) -> Result<()> {
     })
 }
 
-pub fn connect_player(lobby_id: &str, player_name: &str) -> Result<()> {
+// Joins the lobby as a new player, returning the session token needed to reconnect as them
+// The credential is the lobby password or invite code, ignored unless the lobby is password protected
+// The previous session token, if the client had one, lets a ban follow a player who rejoins under a new name
+pub fn connect_player(lobby_id: &str, player_name: &str, credential: &str, previous_session_token: &str) -> GameResult<String> {
+    join_lobby(lobby_id, player_name, credential, previous_session_token, false)
+}
+
+// Joins an existing lobby, even a running one, to watch the board and chat without playing
+pub fn spectate_lobby(lobby_id: &str, player_name: &str, credential: &str, previous_session_token: &str) -> GameResult<String> {
+    join_lobby(lobby_id, player_name, credential, previous_session_token, true)
+}
+
+fn join_lobby(lobby_id: &str, player_name: &str, credential: &str, previous_session_token: &str, spectator: bool) -> GameResult<String> {
     if !(3..=MAX_LOBBY_ID_LENGTH).contains(&lobby_id.len()) {
-        bail!("Lobby ID must be between 3 and {MAX_LOBBY_ID_LENGTH} characters long");
+        return Err(GameError::InvalidLength {
+            field: Field::LobbyId,
+            min: 3,
+            max: MAX_LOBBY_ID_LENGTH,
+        });
     }
     if !(3..=MAX_PLAYER_NAME_LENGTH).contains(&player_name.len()) {
-        bail!("Player name must be between 3 and {MAX_PLAYER_NAME_LENGTH} characters long");
+        return Err(GameError::InvalidLength {
+            field: Field::PlayerName,
+            min: 3,
+            max: MAX_PLAYER_NAME_LENGTH,
+        });
+    }
+    if !regex_match(LOBBY_ID_PATTERN, lobby_id) {
+        return Err(GameError::InvalidCharacters { field: Field::LobbyId });
     }
-    if !regex_match(LOBBY_ID_PATTERN, lobby_id) || !regex_match(PLAYER_NAME_PATTERN, player_name) {
-        bail!("Lobby ID and Player name must be alphabetic");
+    if !regex_match(PLAYER_NAME_PATTERN, player_name) {
+        return Err(GameError::InvalidCharacters { field: Field::PlayerName });
+    }
+    if player_name == "SYSTEM" {
+        return Err(GameError::ReservedName {
+            player_name: player_name.to_owned(),
+        });
     }
-    ensure!(player_name != "SYSTEM", "Player name cannot be 'SYSTEM'");
 
-    if let Err(e) = create_lobby(lobby_id, player_name) {
-        println!("Error creating lobby {e}");
+    if !spectator {
+        if let Err(e) = create_lobby(lobby_id, player_name) {
+            println!("Error creating lobby {e}");
+        }
     }
 
     with_lobby(lobby_id, |lobby| {
-        ensure!(!lobby.players.contains_key(player_name), "Player '{player_name}' already in lobby");
+        if lobby.players.contains_key(player_name) {
+            return Err(GameError::PlayerAlreadyInLobby {
+                player_name: player_name.to_owned(),
+            });
+        }
+        if let Some(ban) = lobby.active_ban(player_name, previous_session_token) {
+            return Err(ban.to_error());
+        }
+        if !lobby.accepts_credential(credential) {
+            return Err(GameError::InvalidCredential);
+        }
+        if !spectator && lobby.players.values().filter(|player| !player.spectator).count() >= lobby.settings.max_players {
+            return Err(GameError::LobbyFull {
+                max_players: lobby.settings.max_players,
+            });
+        }
 
-        lobby.players.entry(player_name.to_owned()).or_insert(Player {
+        let session_token = random_code(32);
+        // Players joining a running team game are put in the smallest team
+        let team = (lobby.settings.team_mode && !spectator && lobby.state != LobbyState::Open).then(|| lobby.smallest_team());
+        lobby.players.entry(player_name.to_owned()).or_insert_with(|| Player {
             name: player_name.to_owned(),
             last_contact: get_current_time(),
-            coins: if lobby.state == LobbyState::Play {
+            joined_at: get_current_time(),
+            connected: true,
+            session_token: session_token.clone(),
+            spectator,
+            team,
+            // Late joiners sat out the rounds already played
+            round_scores: vec![0; lobby.round.saturating_sub(1)],
+            // A late joiner to a pooled team shares the team balance instead
+            coins: if matches!(lobby.state, LobbyState::Play | LobbyState::Paused) && !spectator {
                 lobby.settings.starting_coins + (lobby.elapsed_time / lobby.settings.coin_every_x_seconds as f64).floor() as usize
             } else {
                 0
@@ -365,11 +982,41 @@ pub fn connect_player(lobby_id: &str, player_name: &str) -> Result<()> {
             ..Default::default()
         });
 
-        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_name}' connected"));
+        let joined_as = if spectator { "is spectating" } else { "connected" };
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_name}' {joined_as}"));
+        Ok(session_token)
+    })
+}
+
+// Resumes an existing player after a refresh or dropped connection, keeping their score and coins
+pub fn reconnect_player(lobby_id: &str, player_name: &str, session_token: &str) -> GameResult<()> {
+    with_lobby(lobby_id, |lobby| {
+        if let Some(ban) = lobby.active_ban(player_name, session_token) {
+            return Err(ban.to_error());
+        }
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if session_token.is_empty() || player.session_token != session_token {
+            return Err(GameError::InvalidSession);
+        }
+        player.last_contact = get_current_time();
+        if !player.connected {
+            player.connected = true;
+            add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_name}' reconnected"));
+        }
         Ok(())
     })
 }
 
+fn random_code(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
 pub fn disconnect_player(lobby_id: &str, player_name: &str) {
     let _result = with_lobby(lobby_id, |lobby| {
         add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_name}' left"));
@@ -380,11 +1027,21 @@ pub fn disconnect_player(lobby_id: &str, player_name: &str) {
 
 pub fn alter_lobby_settings(lobby_id: &str, player_name: &str, setting: AlterLobbySetting) {
     let result = with_lobby(lobby_id, |lobby| {
-        ensure!(lobby.state == LobbyState::Open, "Lobby is started");
-        ensure!(player_name == lobby.key_player, "Only the key player can alter the lobby settings");
+        if lobby.state != LobbyState::Open {
+            return Err(GameError::LobbyAlreadyStarted);
+        }
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
         match setting {
             AlterLobbySetting::ItemCount(item_count) => {
-                ensure!((1..=MAX_LOBBY_ITEMS).contains(&item_count), "Items must be 1 to {MAX_LOBBY_ITEMS}");
+                if !(1..=MAX_LOBBY_ITEMS).contains(&item_count) {
+                    return Err(GameError::SettingOutOfRange {
+                        setting: "item_count".to_owned(),
+                        min: 1,
+                        max: MAX_LOBBY_ITEMS,
+                    });
+                }
                 lobby.settings.item_count = item_count;
                 lobby.items_queue.truncate(item_count);
             }
@@ -397,6 +1054,73 @@ pub fn alter_lobby_settings(lobby_id: &str, player_name: &str, setting: AlterLob
             AlterLobbySetting::Theme(theme) => {
                 lobby.settings.theme = theme;
             }
+            AlterLobbySetting::Visibility(visibility) => {
+                lobby.settings.visibility = visibility;
+            }
+            AlterLobbySetting::ScoringCurve(scoring_curve) => {
+                lobby.settings.scoring_curve = scoring_curve;
+            }
+            AlterLobbySetting::QueueMode(queue_mode) => {
+                lobby.settings.queue_mode = queue_mode;
+            }
+            AlterLobbySetting::InformativeRewardKind(reward_kind) => {
+                lobby.settings.informative_reward_kind = reward_kind;
+            }
+            AlterLobbySetting::RewardVoters(reward_voters) => {
+                lobby.settings.reward_voters = reward_voters;
+            }
+            AlterLobbySetting::PublicHints(public_hints) => {
+                lobby.settings.public_hints = public_hints;
+            }
+            AlterLobbySetting::RefundUnvotes(refund_unvotes) => {
+                lobby.settings.refund_unvotes = refund_unvotes;
+            }
+            AlterLobbySetting::TeamMode(team_mode) => {
+                lobby.settings.team_mode = team_mode;
+            }
+            AlterLobbySetting::PooledCoins(pooled_coins) => {
+                lobby.settings.pooled_coins = pooled_coins;
+            }
+            AlterLobbySetting::Teams(teams) => {
+                if !(2..=MAX_TEAMS).contains(&teams.len()) {
+                    return Err(GameError::SettingOutOfRange {
+                        setting: "teams".to_owned(),
+                        min: 2,
+                        max: MAX_TEAMS,
+                    });
+                }
+                for team in &teams {
+                    if !(1..=MAX_TEAM_NAME_LENGTH).contains(&team.len()) {
+                        return Err(GameError::InvalidLength {
+                            field: Field::TeamName,
+                            min: 1,
+                            max: MAX_TEAM_NAME_LENGTH,
+                        });
+                    }
+                    if !regex_match(PLAYER_NAME_PATTERN, team) {
+                        return Err(GameError::InvalidCharacters { field: Field::TeamName });
+                    }
+                }
+                for player in lobby.players.values_mut() {
+                    player.team = player.team.filter(|team| *team < teams.len());
+                }
+                lobby.settings.teams = teams;
+            }
+            AlterLobbySetting::Password(password) => {
+                if password.len() > MAX_LOBBY_PASSWORD_LENGTH {
+                    return Err(GameError::InvalidLength {
+                        field: Field::LobbyPassword,
+                        min: 0,
+                        max: MAX_LOBBY_PASSWORD_LENGTH,
+                    });
+                }
+                if !regex_match(LOBBY_PASSWORD_PATTERN, &password) {
+                    return Err(GameError::InvalidCharacters {
+                        field: Field::LobbyPassword,
+                    });
+                }
+                lobby.password = password;
+            }
             AlterLobbySetting::AddItem(item) => {
                 // If item is empty, pick a random unique word from the difficulty
                 if item.is_empty() {
@@ -404,11 +1128,19 @@ pub fn alter_lobby_settings(lobby_id: &str, player_name: &str, setting: AlterLob
                     return Ok(());
                 }
                 // Else check if the item is valid and add it to the queue
-                ensure!(regex_match(ITEM_NAME_PATTERN, &item), "Item name must be alphabetic");
+                if !regex_match(ITEM_NAME_PATTERN, &item) {
+                    return Err(GameError::InvalidCharacters { field: Field::ItemName });
+                }
                 if !(3..=MAX_ITEM_NAME_LENGTH).contains(&item.len()) {
-                    bail!("Item name must be between 3 and {MAX_ITEM_NAME_LENGTH} characters long");
+                    return Err(GameError::InvalidLength {
+                        field: Field::ItemName,
+                        min: 3,
+                        max: MAX_ITEM_NAME_LENGTH,
+                    });
+                }
+                if lobby.items_queue.contains(&item) {
+                    return Err(GameError::ItemAlreadyExists);
                 }
-                ensure!(!lobby.items_queue.contains(&item), "Item already exists in the lobby");
                 // Capitalise the first letter of the item
                 let item = item
                     .chars()
@@ -434,34 +1166,37 @@ pub fn alter_lobby_settings(lobby_id: &str, player_name: &str, setting: AlterLob
             AlterLobbySetting::RefreshAllItems => {
                 lobby.items_queue.clear();
             }
-            AlterLobbySetting::Advanced(key, value) => match key.as_str() {
-                "starting_coins" => lobby.settings.starting_coins = value,
-                "coin_every_x_seconds" => lobby.settings.coin_every_x_seconds = value,
-                "submit_question_every_x_seconds" => lobby.settings.submit_question_every_x_seconds = value,
-                "add_item_every_x_questions" => lobby.settings.add_item_every_x_questions = value,
-                "submit_question_cost" => lobby.settings.submit_question_cost = value,
-                "masked_question_cost" => lobby.settings.masked_question_cost = value,
-                "guess_item_cost" => lobby.settings.guess_item_cost = value,
-                "question_min_votes" => lobby.settings.question_min_votes = value,
-                "score_to_coins_ratio" => lobby.settings.score_to_coins_ratio = value,
-                _ => bail!("Invalid advanced setting key"),
-            },
+            AlterLobbySetting::Advanced(key, value) => {
+                let Some(&(_, min, max)) = ADVANCED_SETTINGS.iter().find(|(setting, _, _)| *setting == key) else {
+                    return Err(GameError::UnknownSetting { setting: key });
+                };
+                if !(min..=max).contains(&value) {
+                    return Err(GameError::SettingOutOfRange { setting: key, min, max });
+                }
+                let setting = lobby
+                    .settings
+                    .advanced_mut(&key)
+                    .ok_or(GameError::UnknownSetting { setting: key })?;
+                *setting = value;
+            }
         }
         Ok(())
     });
     if let Err(e) = result {
-        alert_popup(lobby_id, player_name, &format!("Setting change failed {e}"));
+        alert_popup(lobby_id, player_name, e);
     }
 }
 
 pub fn start_lobby(lobby_id: &str, player_name: &str) {
     let result = with_lobby(lobby_id, |lobby| {
-        if matches!(lobby.state, LobbyState::Play | LobbyState::Starting) {
-            bail!("Lobby '{lobby_id}' already started");
+        if lobby.state != LobbyState::Open {
+            return Err(GameError::LobbyAlreadyStarted);
         } else if player_name != lobby.key_player {
-            bail!("Only the key player can start the lobby '{lobby_id}'",);
+            return Err(GameError::NotKeyPlayer);
         }
         lobby.state = LobbyState::Starting;
+        lobby.round = 1;
+        lobby.started_player_controlled = lobby.settings.player_controlled;
         if !lobby.settings.player_controlled {
             lobby.items_queue = Vec::new();
         }
@@ -481,13 +1216,179 @@ pub fn start_lobby(lobby_id: &str, player_name: &str) {
         Ok(())
     });
     if let Err(e) = result {
-        alert_popup(lobby_id, player_name, &format!("Start lobby failed {e}"));
+        alert_popup(lobby_id, player_name, e);
     }
 }
 
-pub fn alert_popup(lobby_id: &str, player_name: &str, message: &str) {
+// Freezes the clock, coin grants and question asking until the key player resumes
+pub fn pause_lobby(lobby_id: &str, player_name: &str) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        lobby.ensure_playing()?;
+        lobby.state = LobbyState::Paused;
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' paused the game"));
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+pub fn resume_lobby(lobby_id: &str, player_name: &str) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        if lobby.state != LobbyState::Paused {
+            return Err(GameError::GameNotPaused);
+        }
+        // Restart the clock from now so the paused time isn't counted
+        lobby.state = LobbyState::Play;
+        lobby.last_update = get_current_time();
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' resumed the game"));
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+pub fn join_team(lobby_id: &str, player_name: &str, team: usize) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if !lobby.settings.team_mode {
+            return Err(GameError::TeamModeDisabled);
+        }
+        if lobby.state != LobbyState::Open {
+            return Err(GameError::LobbyAlreadyStarted);
+        }
+        let team_name = lobby.settings.teams.get(team).cloned().ok_or(GameError::TeamNotFound { team })?;
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        player.team = Some(team);
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' joined team {team_name}"));
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+// Takes an ended game back to the lobby for another round, keeping the players, settings and chat
+pub fn rematch_lobby(lobby_id: &str, player_name: &str) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if lobby.state != LobbyState::Ended {
+            return Err(GameError::GameNotEnded);
+        }
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        lobby.state = LobbyState::Open;
+        lobby.round = 0;
+        lobby.elapsed_time = 0.0;
+        lobby.overtime = false;
+        lobby.last_update = get_current_time();
+        lobby.questions_queue.clear();
+        lobby.quizmaster_queue.clear();
+        lobby.items.clear();
+        lobby.items_queue.clear();
+        lobby.questions.clear();
+        lobby.coins_countdown = 0.0;
+        lobby.questions_queue_countdown = 0.0;
+        lobby.items_counter = 0;
+        lobby.questions_counter = 0;
+        lobby.queued_questions_counter = 0;
+        lobby.team_coins.clear();
+        lobby.settings.player_controlled = lobby.started_player_controlled;
+        for player in lobby.players.values_mut() {
+            player.score = 0;
+            player.round_scores.clear();
+            player.round_score = 0;
+            player.coins = 0;
+            player.quizmaster = false;
+        }
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' set up a rematch"));
+        println!("Lobby '{lobby_id}' reset for a rematch by key player '{player_name}'");
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+pub fn transfer_key_player(lobby_id: &str, player_name: &str, new_key_player: &str) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        let new_player = lobby
+            .players
+            .get(new_key_player)
+            .filter(|player| player.name != player_name)
+            .ok_or_else(|| GameError::PlayerNotFound {
+                player_name: new_key_player.to_owned(),
+            })?;
+        if new_player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+
+        // Only hosting moves, a quizmaster keeps answering until their round is over
+        new_key_player.clone_into(&mut lobby.key_player);
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' made '{new_key_player}' the host"));
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+// Picks a new key player after the previous one left, preferring connected players who joined earliest
+// Spectators only take over when nobody playing is left
+fn promote_key_player(lobby: &mut Lobby) {
+    let Some(new_key_player) = lobby
+        .players
+        .values()
+        .min_by(|a, b| {
+            a.spectator
+                .cmp(&b.spectator)
+                .then(b.connected.cmp(&a.connected))
+                .then(a.joined_at.total_cmp(&b.joined_at))
+        })
+        .map(|player| player.name.clone())
+    else {
+        return;
+    };
+    println!(
+        "Lobby '{}' key player '{}' left, promoting '{new_key_player}'",
+        lobby.id, lobby.key_player
+    );
+    lobby.key_player.clone_from(&new_key_player);
+    add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{new_key_player}' is now the host"));
+}
+
+// The quizmaster left mid game, so the AI takes over answering, including questions they hadn't answered yet
+fn replace_missing_quizmaster(lobby: &mut Lobby) {
+    if lobby.state == LobbyState::Play && lobby.settings.player_controlled && !lobby.players.values().any(|player| player.quizmaster) {
+        lobby.settings.player_controlled = false;
+        let unanswered = std::mem::take(&mut lobby.quizmaster_queue);
+        let queued_at = lobby.elapsed_time;
+        lobby.questions_queue.extend(unanswered.into_iter().map(|question| QueuedQuestion {
+            answers: HashMap::new(),
+            queued_at,
+            ..question
+        }));
+        add_chat_message_to_lobby(lobby, "SYSTEM", "The quizmaster left, the AI will answer questions from now on");
+    }
+}
+
+pub fn alert_popup(lobby_id: &str, player_name: &str, error: GameError) {
     let result = with_player(lobby_id, player_name, |_, player| {
-        player.messages.push(PlayerMessage::AlertPopup(message.to_owned()));
+        player.messages.push(PlayerMessage::AlertPopup(error));
         Ok(())
     });
     if let Err(e) = result {
@@ -495,40 +1396,99 @@ pub fn alert_popup(lobby_id: &str, player_name: &str, message: &str) {
     }
 }
 
-pub fn kick_player(lobby_id: &str, player_name: &str, player_to_kick: &str) {
-    let result = with_player(lobby_id, player_to_kick, |_, player| {
-        player.messages.push(PlayerMessage::PlayerKicked);
+// The reason is shown to the kicked player, a ban also stops them rejoining by name or from the same session
+pub fn kick_player(lobby_id: &str, player_name: &str, player_to_kick: &str, reason: &str, ban_length: BanLength) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        if player_name == player_to_kick {
+            return Err(GameError::CannotKickSelf);
+        }
+        if reason.len() > MAX_KICK_REASON_LENGTH {
+            return Err(GameError::InvalidLength {
+                field: Field::KickReason,
+                min: 0,
+                max: MAX_KICK_REASON_LENGTH,
+            });
+        }
+        let player = lobby.players.get_mut(player_to_kick).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_to_kick.to_owned(),
+        })?;
+        player.messages.push(PlayerMessage::PlayerKicked(reason.to_owned()));
+        let session_token = player.session_token.clone();
+
+        let expires_at = match ban_length {
+            BanLength::NoBan => None,
+            BanLength::Minutes(minutes) => Some(Some((minutes as f64).mul_add(60.0, get_current_time()))),
+            BanLength::Permanent => Some(None),
+        };
+        if let Some(expires_at) = expires_at {
+            lobby.bans.retain(|ban| ban.player_name != player_to_kick);
+            lobby.bans.push(Ban {
+                player_name: player_to_kick.to_owned(),
+                session_token,
+                reason: reason.to_owned(),
+                expires_at,
+            });
+        }
+
+        let action = if expires_at.is_some() { "banned" } else { "kicked" };
+        let message = if reason.is_empty() {
+            format!("Player '{player_to_kick}' was {action}")
+        } else {
+            format!("Player '{player_to_kick}' was {action}: {reason}")
+        };
+        add_chat_message_to_lobby(lobby, "SYSTEM", &message);
         Ok(())
     });
-    add_chat_message(lobby_id, "SYSTEM", &format!("Player '{player_to_kick}' was kicked"));
     if let Err(e) = result {
-        alert_popup(lobby_id, player_name, &format!("Kick failed {e}"));
+        alert_popup(lobby_id, player_name, e);
     }
 }
 
-pub fn add_chat_message(lobby_id: &str, player_name: &str, message: &str) {
-    let error_message = if message.is_empty() {
-        Some("Chat message must be at least 1 character long".to_string())
-    } else if message.len() > MAX_CHAT_LENGTH {
-        Some(format!("Chat message must be less than {MAX_CHAT_LENGTH} characters long"))
-    } else {
-        let res = with_lobby(lobby_id, |lobby| {
+pub fn unban_player(lobby_id: &str, player_name: &str, player_to_unban: &str) {
+    let result = with_lobby(lobby_id, |lobby| {
+        if player_name != lobby.key_player {
+            return Err(GameError::NotKeyPlayer);
+        }
+        lobby.bans.retain(|ban| ban.player_name != player_to_unban);
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_to_unban}' was unbanned"));
+        Ok(())
+    });
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
+    }
+}
+
+// Team only messages go to the sender's team, and fall back to the open chat outside of team games
+pub fn add_chat_message(lobby_id: &str, player_name: &str, message: &str, team_only: bool) {
+    let result = if (1..=MAX_CHAT_LENGTH).contains(&message.len()) {
+        with_lobby(lobby_id, |lobby| {
+            let team = lobby
+                .players
+                .get(player_name)
+                .and_then(|player| player.team)
+                .filter(|_| team_only && lobby.settings.team_mode);
             lobby.chat_messages.push(ChatMessage {
                 player: player_name.to_owned(),
                 message: message.to_owned(),
+                team,
             });
             if lobby.chat_messages.len() > MAX_CHAT_MESSAGES {
                 lobby.chat_messages.remove(0);
             }
             Ok(())
-        });
-        match res {
-            Ok(()) => None,
-            Err(e) => Some(format!("Chat message failed to send {e}")),
-        }
+        })
+    } else {
+        Err(GameError::InvalidLength {
+            field: Field::ChatMessage,
+            min: 1,
+            max: MAX_CHAT_LENGTH,
+        })
     };
-    if let Some(msg) = error_message {
-        alert_popup(lobby_id, player_name, &msg);
+    if let Err(e) = result {
+        alert_popup(lobby_id, player_name, e);
     }
 }
 
@@ -536,18 +1496,20 @@ pub fn add_chat_message_to_lobby(lobby: &mut Lobby, player_name: &str, message:
     lobby.chat_messages.push(ChatMessage {
         player: player_name.to_owned(),
         message: message.to_owned(),
+        team: None,
     });
     if lobby.chat_messages.len() > MAX_CHAT_MESSAGES {
         lobby.chat_messages.remove(0);
     }
 }
 
-pub fn get_state(lobby_id: &str, player_name: &str) -> Result<(Lobby, Vec<PlayerMessage>)> {
+pub fn get_state(lobby_id: &str, player_name: &str) -> GameResult<(Lobby, Vec<PlayerMessage>)> {
     let mut should_kick = false;
     let result = with_player(lobby_id, player_name, |lobby, player| {
         player.last_contact = get_current_time();
+        player.connected = true;
         let messages = player.messages.clone();
-        if messages.contains(&PlayerMessage::PlayerKicked) {
+        if messages.iter().any(|message| matches!(message, PlayerMessage::PlayerKicked(_))) {
             should_kick = true;
         }
         player.messages.clear();
@@ -562,11 +1524,6 @@ pub fn get_state(lobby_id: &str, player_name: &str) -> Result<(Lobby, Vec<Player
     result
 }
 
-pub fn get_current_time() -> f64 {
-    let now = time::SystemTime::now();
-    now.duration_since(time::UNIX_EPOCH).unwrap_or_default().as_secs_f64()
-}
-
 fn regex_match(pattern: &str, haystack: &str) -> bool {
     Regex::new(pattern).unwrap().is_match(haystack)
 }
@@ -579,31 +1536,38 @@ pub fn lobby_loop() {
     lobbys_lock.retain(|lobby_id, lobby| {
         let current_time = get_current_time();
 
-        // Remove inactive players and check if key player is active
+        // Mark idle players disconnected, then remove them once they miss the reconnect grace period
+        let mut players_disconnected = Vec::new();
         let mut players_kicked = Vec::new();
         lobby.players.retain(|player_id, player| {
-            if current_time - player.last_contact > IDLE_KICK_TIME {
+            let idle_time = current_time - player.last_contact;
+            if idle_time > IDLE_KICK_TIME + RECONNECT_GRACE_TIME {
                 players_kicked.push(player_id.clone());
                 println!("Kicking player '{player_id}' due to idle");
-                false
-            } else {
-                true
+                return false;
+            }
+            if idle_time > IDLE_KICK_TIME && player.connected {
+                player.connected = false;
+                players_disconnected.push(player_id.clone());
             }
+            true
         });
+        for player_id in players_disconnected {
+            add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_id}' disconnected"));
+        }
         for player_id in players_kicked {
             add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Player '{player_id}' left"));
         }
-        let key_player_active = lobby.players.contains_key(&lobby.key_player);
+        if !lobby.players.contains_key(&lobby.key_player) {
+            promote_key_player(lobby);
+        }
+        replace_missing_quizmaster(lobby);
 
-        // Remove lobby if key player is inactive or no players are left
-        if !key_player_active || lobby.players.is_empty() {
-            println!("Removing lobby '{lobby_id}' due to no key player or no players");
+        // Remove lobby if no players are left
+        if lobby.players.is_empty() {
+            println!("Removing lobby '{lobby_id}' due to no players");
             false
         } else {
-            if lobby.state == LobbyState::Ended && lobby.elapsed_time > 10.0 {
-                println!("Removing lobby '{lobby_id}' due to ended and elapsed time");
-                return false;
-            }
             // Update lobby state if lobby is started
             if lobby.state == LobbyState::Play {
                 let elapsed_time_update = current_time - lobby.last_update;
@@ -612,16 +1576,31 @@ pub fn lobby_loop() {
                 lobby.coins_countdown -= elapsed_time_update;
                 if lobby.coins_countdown <= 0.0 {
                     lobby.coins_countdown += lobby.settings.coin_every_x_seconds as f64;
+                    // A pooled team earns a coin for each of its connected players
+                    let earners = lobby
+                        .players
+                        .values()
+                        .filter(|player| !player.quizmaster && !player.spectator && player.connected)
+                        .map(|player| player.name.clone())
+                        .collect::<Vec<_>>();
+                    for player_name in &earners {
+                        if let Some(coins) = lobby.coins_mut(player_name) {
+                            *coins += 1;
+                        }
+                    }
+                    let pooled_teams = earners
+                        .iter()
+                        .filter_map(|player_name| lobby.pooled_team(player_name))
+                        .collect::<Vec<_>>();
                     for player in lobby.players.values_mut() {
-                        if !player.quizmaster {
-                            player.coins += 1;
+                        if earners.contains(&player.name) || player.team.is_some_and(|team| pooled_teams.contains(&team)) {
                             player.messages.push(PlayerMessage::CoinGiven);
                         }
                     }
                 }
 
                 // If lobby has a queued question with at least QUESTION_MIN_VOTES votes, tick it down, else reset
-                if lobby.questions_queue_active() {
+                if lobby.questions_queue_active() && !lobby.overtime {
                     lobby.questions_queue_countdown -= elapsed_time_update;
                     if lobby.questions_queue_countdown <= 0.0 {
                         lobby.questions_queue_countdown += lobby.settings.submit_question_every_x_seconds as f64;
@@ -637,14 +1616,25 @@ pub fn lobby_loop() {
                 // Update the elapsed time and last update time for the lobby
                 lobby.elapsed_time += elapsed_time_update;
                 lobby.last_update = current_time;
+                expire_queued_questions(lobby);
+
+                // When the time limit runs out questions stop for a final round of guesses, then the remaining items are revealed
+                if lobby.time_left() == Some(0.0) {
+                    if lobby.overtime {
+                        reveal_remaining_items(lobby);
+                    } else {
+                        start_overtime(lobby);
+                    }
+                }
             } else {
                 // Keep the items queue topped up
                 if lobby.items_queue.len() > lobby.settings.item_count {
                     lobby.items_queue.truncate(lobby.settings.item_count);
                 }
+                // Later rounds of a quizmaster series get generated items, the quizmaster only picks them for the first
                 if lobby.items_queue.len() < lobby.settings.item_count
                     && ((lobby.state == LobbyState::Open && lobby.settings.player_controlled)
-                        || (lobby.state == LobbyState::Starting && !lobby.settings.player_controlled))
+                        || (lobby.state == LobbyState::Starting && (!lobby.settings.player_controlled || lobby.round > 1)))
                 {
                     lobbies_needing_words.push(lobby_id.clone());
                 }
@@ -654,11 +1644,61 @@ pub fn lobby_loop() {
                     lobby.state = LobbyState::Play;
                     lobby.last_update = get_current_time();
 
+                    // The key player is the first quizmaster, later rounds of a series already picked the next one
+                    // A spectating key player can't answer, so the earliest player to join does, or the AI when nobody is playing
+                    if lobby.settings.player_controlled && !lobby.players.values().any(|player| player.quizmaster) {
+                        let quizmaster = lobby
+                            .players
+                            .get(&lobby.key_player)
+                            .filter(|player| !player.spectator)
+                            .or_else(|| {
+                                lobby
+                                    .players
+                                    .values()
+                                    .filter(|player| !player.spectator)
+                                    .min_by(|a, b| a.joined_at.total_cmp(&b.joined_at))
+                            })
+                            .map(|player| player.name.clone());
+                        if let Some(player) = quizmaster.and_then(|quizmaster| lobby.players.get_mut(&quizmaster)) {
+                            player.quizmaster = true;
+                        } else {
+                            lobby.settings.player_controlled = false;
+                            add_chat_message_to_lobby(lobby, "SYSTEM", "Nobody is playing to be quizmaster, the AI will answer questions");
+                        }
+                    }
+                    for player in lobby.players.values_mut() {
+                        // The quizmaster answers for everyone, so they play for no team
+                        if player.quizmaster {
+                            player.team = None;
+                        }
+                    }
+
+                    // Anyone who didn't pick a team is put in the smallest one, pooled teams start with everyone's coins combined
+                    if lobby.settings.team_mode {
+                        let mut unassigned = lobby
+                            .players
+                            .values()
+                            .filter(|player| player.team.is_none() && !player.spectator && !player.quizmaster)
+                            .map(|player| player.name.clone())
+                            .collect::<Vec<_>>();
+                        unassigned.sort();
+                        for player_name in unassigned {
+                            let team = lobby.smallest_team();
+                            if let Some(player) = lobby.players.get_mut(&player_name) {
+                                player.team = Some(team);
+                            }
+                        }
+                    }
+                    lobby.team_coins = (0..lobby.settings.teams.len())
+                        .map(|team| {
+                            lobby.settings.starting_coins * lobby.players.values().filter(|player| player.team == Some(team)).count()
+                        })
+                        .collect();
+
                     for player in lobby.players.values_mut() {
                         player.messages.push(PlayerMessage::GameStart);
-                        player.coins = lobby.settings.starting_coins;
-                        if player.name == lobby.key_player && lobby.settings.player_controlled {
-                            player.quizmaster = true;
+                        if !player.spectator {
+                            player.coins = lobby.settings.starting_coins;
                         }
                     }
 
@@ -676,3 +1716,309 @@ pub fn lobby_loop() {
         topup_lobby_if_available(&lobby_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::TestLobby;
+
+    #[test]
+    fn coins_are_given_every_interval() {
+        let test = TestLobby::new("clockcoins", &["alice"]);
+        test.play();
+
+        test.step(0.0);
+        assert_eq!(test.coins("alice"), 1);
+
+        test.step(3.0);
+        assert_eq!(test.coins("alice"), 1);
+
+        test.step(3.0);
+        assert_eq!(test.coins("alice"), 2);
+        let elapsed_time = test.with(|lobby| lobby.elapsed_time);
+        assert!((elapsed_time - 6.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn question_countdown_resets_without_votes() {
+        let test = TestLobby::new("clockqueue", &["alice"]);
+        test.play();
+
+        test.step(4.0);
+        let countdown = test.with(|lobby| lobby.questions_queue_countdown);
+        assert!((countdown - 10.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn idle_players_disconnect_then_leave() {
+        let test = TestLobby::new("clockidle", &["alice", "bob"]);
+
+        test.clock.advance(IDLE_KICK_TIME + 1.0);
+        test.touch("alice");
+        lobby_loop();
+        let (bob_connected, key_player) = test.with(|lobby| (lobby.players["bob"].connected, lobby.key_player.clone()));
+        assert!(!bob_connected);
+        assert_eq!(key_player, "alice");
+
+        test.clock.advance(RECONNECT_GRACE_TIME);
+        test.touch("alice");
+        lobby_loop();
+        assert!(test.with(|lobby| !lobby.players.contains_key("bob")));
+    }
+
+    #[test]
+    fn empty_lobbies_are_removed() {
+        let test = TestLobby::new("clockempty", &["alice"]);
+
+        test.clock.advance(IDLE_KICK_TIME + RECONNECT_GRACE_TIME + 1.0);
+        lobby_loop();
+        assert!(matches!(with_lobby("clockempty", |_| Ok(())), Err(GameError::LobbyNotFound { .. })));
+    }
+
+    #[test]
+    fn quizmaster_plays_for_no_team() {
+        let test = TestLobby::new("clockteams", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.player_controlled = true;
+            lobby.settings.team_mode = true;
+            lobby.settings.pooled_coins = true;
+            lobby.players.get_mut("alice").unwrap().team = Some(0);
+        });
+
+        test.start();
+        let teams = test.with(|lobby| {
+            ["alice", "bob", "carol"].map(|player_name| (lobby.players[player_name].quizmaster, lobby.players[player_name].team))
+        });
+        assert_eq!(teams, [(true, None), (false, Some(0)), (false, Some(1))]);
+        assert_eq!(test.coins("bob"), 8);
+    }
+
+    #[test]
+    fn pooled_teams_share_one_balance() {
+        let test = TestLobby::new("clockpool", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.team_mode = true;
+            lobby.settings.pooled_coins = true;
+            lobby.players.get_mut("alice").unwrap().team = Some(0);
+            lobby.players.get_mut("bob").unwrap().team = Some(0);
+        });
+
+        test.start();
+        assert_eq!(test.with(|lobby| lobby.team_coins.clone()), vec![16, 8]);
+
+        // Each connected teammate earns a coin for the team
+        test.step(0.0);
+        assert_eq!(test.with(|lobby| lobby.team_coins.clone()), vec![18, 9]);
+
+        test.with(|lobby| *lobby.coins_mut("alice").unwrap() -= 5);
+        assert_eq!(test.coins("bob"), 13);
+        assert_eq!(test.coins("carol"), 9);
+    }
+
+    #[test]
+    fn time_limit_is_range_checked() {
+        let test = TestLobby::new("clocklimit", &["alice"]);
+        alter_lobby_settings(
+            "clocklimit",
+            "alice",
+            AlterLobbySetting::Advanced("time_limit_minutes".to_owned(), usize::MAX),
+        );
+        alter_lobby_settings(
+            "clocklimit",
+            "alice",
+            AlterLobbySetting::Advanced("final_guess_seconds".to_owned(), 60),
+        );
+        let mut lobby = test.with(|lobby| lobby.clone());
+        assert_eq!(lobby.settings.time_limit_minutes, 0);
+        assert_eq!(lobby.settings.final_guess_seconds, 60);
+
+        // Even a limit that skipped validation can't overflow the deadline
+        lobby.settings.time_limit_minutes = usize::MAX;
+        assert!(lobby.time_left().is_some_and(|time_left| time_left > 0.0));
+    }
+
+    #[test]
+    fn results_of_a_running_game_are_not_ready() {
+        let test = TestLobby::new("clockrunning", &["alice"]);
+        test.play();
+
+        assert!(matches!(get_finished_game("clockrunning", ""), Err(GameError::GameNotEnded)));
+        assert!(matches!(
+            get_finished_game("clocknolobby", ""),
+            Err(GameError::LobbyNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn private_scoreboards_need_a_credential() {
+        let test = TestLobby::new("clockprivate", &["alice"]);
+        let invite_code = test.with(|lobby| {
+            lobby.settings.visibility = Visibility::Password;
+            lobby.password = "secret".to_owned();
+            lobby.invite_code.clone()
+        });
+
+        assert!(matches!(get_scoreboard("clockprivate", ""), Err(GameError::InvalidCredential)));
+        assert!(get_scoreboard("clockprivate", "secret").is_ok());
+        assert!(get_scoreboard("clockprivate", &invite_code).is_ok());
+    }
+
+    #[test]
+    fn game_starts_once_items_are_ready() {
+        let test = TestLobby::new("clockstart", &["alice"]);
+
+        test.start();
+        let (state, items, alice_coins) = test.with(|lobby| (lobby.state.clone(), lobby.items.len(), lobby.coins("alice")));
+        assert!(state == LobbyState::Play);
+        assert_eq!(items, 2);
+        assert_eq!(alice_coins, 8);
+    }
+
+    #[test]
+    fn timed_bans_expire() {
+        let test = TestLobby::new("clockban", &["alice", "bob"]);
+        let session_token = test.with(|lobby| lobby.players["bob"].session_token.clone());
+
+        kick_player("clockban", "alice", "bob", "", BanLength::Minutes(5));
+        disconnect_player("clockban", "bob");
+        assert!(matches!(
+            connect_player("clockban", "bob", "", ""),
+            Err(GameError::Banned { minutes_left: Some(5) })
+        ));
+        // A new name doesn't get around the ban from the same session
+        assert!(test.with(|lobby| lobby.active_ban("robert", &session_token).is_some()));
+
+        test.step(4.0 * 60.0);
+        assert!(matches!(
+            connect_player("clockban", "bob", "", ""),
+            Err(GameError::Banned { minutes_left: Some(1) })
+        ));
+
+        test.step(60.0);
+        assert!(test.with(|lobby| lobby.active_ban("bob", &session_token).is_none() && lobby.bans.is_empty()));
+    }
+
+    #[test]
+    fn quizmaster_stays_when_the_host_changes_mid_game() {
+        let test = TestLobby::new("hostswap", &["alice", "bob"]);
+        test.with(|lobby| lobby.settings.player_controlled = true);
+        test.start();
+
+        transfer_key_player("hostswap", "alice", "bob");
+        let roles = test.with(|lobby| {
+            (
+                lobby.key_player.clone(),
+                lobby.players["alice"].quizmaster,
+                lobby.players["bob"].quizmaster,
+            )
+        });
+        assert_eq!(roles, ("bob".to_owned(), true, false));
+    }
+
+    #[test]
+    fn spectating_hosts_are_not_made_quizmaster() {
+        let test = TestLobby::new("specquiz", &["alice", "bob"]);
+        test.with(|lobby| {
+            lobby.settings.player_controlled = true;
+            lobby.players.get_mut("alice").unwrap().spectator = true;
+        });
+
+        test.start();
+        assert_eq!(
+            test.with(|lobby| (lobby.players["alice"].quizmaster, lobby.players["bob"].quizmaster)),
+            (false, true)
+        );
+    }
+
+    #[test]
+    fn ai_answers_when_only_spectators_are_left() {
+        let test = TestLobby::new("specai", &["alice"]);
+        test.with(|lobby| {
+            lobby.settings.player_controlled = true;
+            lobby.players.get_mut("alice").unwrap().spectator = true;
+        });
+
+        test.start();
+        assert!(test.with(|lobby| !lobby.settings.player_controlled && !lobby.players["alice"].quizmaster));
+    }
+
+    #[test]
+    fn rematch_brings_the_quizmaster_back() {
+        let test = TestLobby::new("rematchquiz", &["alice", "bob"]);
+        test.with(|lobby| lobby.settings.player_controlled = true);
+        start_lobby("rematchquiz", "alice");
+        test.start();
+
+        disconnect_player("rematchquiz", "alice");
+        test.step(0.0);
+        assert!(test.with(|lobby| !lobby.settings.player_controlled));
+
+        test.with(|lobby| lobby.state = LobbyState::Ended);
+        rematch_lobby("rematchquiz", "bob");
+        assert!(test.with(|lobby| lobby.settings.player_controlled));
+    }
+
+    #[test]
+    fn advanced_settings_are_range_checked() {
+        let test = TestLobby::new("advrange", &["alice"]);
+        for (key, value) in [
+            ("coin_every_x_seconds", 0),
+            ("submit_question_every_x_seconds", 0),
+            ("hint_cost", 101),
+            ("no_such_setting", 1),
+        ] {
+            alter_lobby_settings("advrange", "alice", AlterLobbySetting::Advanced(key.to_owned(), value));
+        }
+        alter_lobby_settings("advrange", "alice", AlterLobbySetting::Advanced("starting_coins".to_owned(), 20));
+
+        let mut settings = test.with(|lobby| lobby.settings.clone());
+        assert_eq!(
+            (
+                settings.coin_every_x_seconds,
+                settings.submit_question_every_x_seconds,
+                settings.hint_cost,
+                settings.starting_coins
+            ),
+            (6, 10, 8, 20)
+        );
+        assert!(ADVANCED_SETTINGS.iter().all(|(key, _, _)| settings.advanced_mut(key).is_some()));
+    }
+
+    #[test]
+    fn paused_time_is_not_counted() {
+        let test = TestLobby::new("pausetime", &["alice"]);
+        test.with(|lobby| lobby.settings.time_limit_minutes = 1);
+        test.play();
+        test.step(10.0);
+        let coins_before_pause = test.coins("alice");
+
+        pause_lobby("pausetime", "alice");
+        test.step(100.0);
+        let paused = test.with(|lobby| (lobby.elapsed_time, lobby.time_left()));
+        assert_eq!(paused, (10.0, Some(50.0)));
+        assert_eq!(test.coins("alice"), coins_before_pause);
+
+        resume_lobby("pausetime", "alice");
+        test.step(5.0);
+        let resumed = test.with(|lobby| (lobby.elapsed_time, lobby.time_left()));
+        assert_eq!(resumed, (15.0, Some(45.0)));
+    }
+
+    #[test]
+    fn auction_balances_are_sealed() {
+        let test = TestLobby::new("auctionseal", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.team_mode = true;
+            lobby.settings.pooled_coins = true;
+            lobby.players.get_mut("alice").unwrap().team = Some(0);
+            lobby.players.get_mut("bob").unwrap().team = Some(0);
+        });
+        test.start();
+        let seen_by_alice =
+            |lobby: &Lobby| ["alice", "bob", "carol"].map(|player_name| lobby.players[player_name].reduce(lobby, "alice").coins);
+
+        assert_eq!(test.with(|lobby| seen_by_alice(lobby)), [Some(16), Some(16), Some(8)]);
+        test.with(|lobby| lobby.settings.queue_mode = QueueMode::Auction);
+        assert_eq!(test.with(|lobby| seen_by_alice(lobby)), [Some(16), Some(16), None]);
+    }
+}
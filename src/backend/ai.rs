@@ -0,0 +1,30 @@
+use crate::backend::openai::query_openai;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::{Arc, LazyLock, RwLock};
+
+// Answers the prompts the game uses for question validation, answering and item generation
+// Prompts are '|' separated messages each prefixed with a role, u: user, s: system, a: assistant
+pub trait AiProvider: Send + Sync {
+    fn query<'a>(&'a self, prompt: &'a str, max_tokens: usize, temperature: f32, use_json: bool) -> BoxFuture<'a, Result<String>>;
+}
+
+// Queries the OpenAI API, used by the server
+pub struct OpenAiProvider;
+
+impl AiProvider for OpenAiProvider {
+    fn query<'a>(&'a self, prompt: &'a str, max_tokens: usize, temperature: f32, use_json: bool) -> BoxFuture<'a, Result<String>> {
+        Box::pin(query_openai(prompt, max_tokens, temperature, use_json))
+    }
+}
+
+static AI_PROVIDER: LazyLock<RwLock<Arc<dyn AiProvider>>> = LazyLock::new(|| RwLock::new(Arc::new(OpenAiProvider)));
+
+pub fn set_ai_provider(provider: Arc<dyn AiProvider>) {
+    *AI_PROVIDER.write().unwrap() = provider;
+}
+
+pub async fn query_ai(prompt: &str, max_tokens: usize, temperature: f32, use_json: bool) -> Result<String> {
+    let provider = AI_PROVIDER.read().unwrap().clone();
+    provider.query(prompt, max_tokens, temperature, use_json).await
+}
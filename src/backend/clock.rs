@@ -0,0 +1,55 @@
+use std::{
+    sync::{Arc, LazyLock, Mutex, RwLock},
+    time,
+};
+
+// A source of the current time in seconds, lets game timing be driven manually in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> f64;
+}
+
+// Reads the wall clock, used by the server
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        let now = time::SystemTime::now();
+        now.duration_since(time::UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+    }
+}
+
+// Only moves when told to, so countdowns and timeouts can be stepped through precisely
+#[derive(Default)]
+pub struct ManualClock {
+    time: Mutex<f64>,
+}
+
+impl ManualClock {
+    pub const fn new(start: f64) -> Self {
+        Self { time: Mutex::new(start) }
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        *self.time.lock().unwrap() += seconds;
+    }
+
+    pub fn set(&self, time: f64) {
+        *self.time.lock().unwrap() = time;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        *self.time.lock().unwrap()
+    }
+}
+
+static CLOCK: LazyLock<RwLock<Arc<dyn Clock>>> = LazyLock::new(|| RwLock::new(Arc::new(SystemClock)));
+
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write().unwrap() = clock;
+}
+
+pub fn get_current_time() -> f64 {
+    CLOCK.read().unwrap().now()
+}
@@ -0,0 +1,125 @@
+use serde::Serialize;
+use strum_macros::Display;
+use thiserror::Error;
+
+pub type GameResult<T> = Result<T, GameError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    #[strum(serialize = "Lobby ID")]
+    LobbyId,
+    #[strum(serialize = "Player name")]
+    PlayerName,
+    #[strum(serialize = "Lobby password")]
+    LobbyPassword,
+    #[strum(serialize = "Item name")]
+    ItemName,
+    #[strum(serialize = "Chat message")]
+    ChatMessage,
+    #[strum(serialize = "Kick reason")]
+    KickReason,
+    #[strum(serialize = "Team name")]
+    TeamName,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GameError {
+    #[error("Lobby '{lobby_id}' not found")]
+    LobbyNotFound { lobby_id: String },
+    #[error("Player '{player_name}' not found")]
+    PlayerNotFound { player_name: String },
+    #[error("Item {item_id} not found")]
+    ItemNotFound { item_id: usize },
+    #[error("Question not found")]
+    QuestionNotFound { question_id: usize },
+
+    #[error("{field} must be between {min} and {max} characters long")]
+    InvalidLength { field: Field, min: usize, max: usize },
+    #[error("{field} contains invalid characters")]
+    InvalidCharacters { field: Field },
+    #[error("Player name '{player_name}' is reserved")]
+    ReservedName { player_name: String },
+    #[error("Player '{player_name}' already in lobby")]
+    PlayerAlreadyInLobby { player_name: String },
+    #[error("Session expired, please join again")]
+    InvalidSession,
+    #[error("Incorrect lobby password or invite code")]
+    InvalidCredential,
+    #[error("Lobby is full, it allows at most {max_players} players")]
+    LobbyFull { max_players: usize },
+    #[error("You are banned from this lobby{}", .minutes_left.map_or_else(String::new, |minutes| format!(" for another {minutes} minutes")))]
+    Banned { minutes_left: Option<usize> },
+    #[error("You can't kick yourself")]
+    CannotKickSelf,
+
+    #[error("Lobby not started")]
+    LobbyNotStarted,
+    #[error("Lobby already started")]
+    LobbyAlreadyStarted,
+    #[error("Time is up, only guesses are allowed now")]
+    TimeUp,
+    #[error("Game is paused")]
+    GamePaused,
+    #[error("Game is not paused")]
+    GameNotPaused,
+    #[error("Game has not ended yet")]
+    GameNotEnded,
+    #[error("Only the key player can do this")]
+    NotKeyPlayer,
+    #[error("Only the quizmaster can do this")]
+    NotQuizmaster,
+    #[error("Quizmaster cannot engage")]
+    QuizmasterCannotEngage,
+    #[error("Spectators can only watch and chat")]
+    SpectatorCannotEngage,
+    #[error("Team mode is not enabled")]
+    TeamModeDisabled,
+    #[error("Team {team} not found")]
+    TeamNotFound { team: usize },
+
+    #[error("Insufficient coins, {required} needed but {available} available")]
+    InsufficientCoins { required: usize, available: usize },
+    #[error("Insufficient score")]
+    InsufficientScore,
+
+    #[error("Setting '{setting}' must be {min} to {max}")]
+    SettingOutOfRange { setting: String, min: usize, max: usize },
+    #[error("Invalid setting '{setting}'")]
+    UnknownSetting { setting: String },
+    #[error("Item already exists in the lobby")]
+    ItemAlreadyExists,
+
+    #[error("Question already exists in queue")]
+    QuestionAlreadyQueued,
+    #[error("Only the player who asked the question can do this")]
+    NotQuestionOwner,
+    #[error("Questions are auctioned in this lobby, place a bid instead")]
+    VotingDisabled,
+    #[error("Questions are voted on in this lobby, bidding is off")]
+    BiddingDisabled,
+    #[error("Bids must be at least 1 coin")]
+    InvalidBid,
+    #[error("You haven't voted for this question")]
+    NotVoted,
+    #[error("Question unsuitable, {reason}")]
+    QuestionUnsuitable { reason: String },
+    #[error("No questions in queue")]
+    NoQuestionsQueued,
+    #[error("Question needs at least {required} votes")]
+    NotEnoughVotes { required: usize },
+    #[error("Question needs bids totalling at least {required} coins")]
+    NotEnoughBids { required: usize },
+    #[error("Failed to get answers for question '{question}'")]
+    AnswersUnavailable { question: String },
+
+    #[error("You already know that hint")]
+    HintAlreadyKnown,
+    #[error("Every letter of that item has been revealed")]
+    NoHintsLeft,
+    #[error("You can already see that question")]
+    QuestionAlreadyVisible,
+    #[error("Incorrect guess")]
+    IncorrectGuess,
+}
@@ -1,9 +1,13 @@
 use crate::backend::{
-    add_chat_message_to_lobby, alert_popup, openai::query_ai, with_lobby, Answer, Item, Lobby, LobbyState, PlayerMessage, Question,
-    QueuedQuestion,
+    add_chat_message_to_lobby,
+    ai::query_ai,
+    alert_popup,
+    error::{GameError, GameResult},
+    record_finished_game, with_lobby, Answer, Hint, HintKind, Item, Lobby, LobbyState, PlayerMessage, Question, QueueMode, QueuedQuestion,
+    RewardKind,
 };
-use anyhow::{anyhow, bail, ensure, Result};
 use futures::future::join_all;
+use rand::{seq::SliceRandom, thread_rng};
 use serde::Deserialize;
 use std::{cmp::Ordering, collections::HashMap, str::FromStr};
 
@@ -17,6 +21,8 @@ pub fn add_item_to_lobby(lobby: &mut Lobby) {
         name: item_name,
         id: lobby.items_counter + 1,
         answers: HashMap::new(),
+        hints: Vec::new(),
+        clue: None,
     });
     lobby.items_counter += 1;
     for player in lobby.players.values_mut() {
@@ -29,9 +35,11 @@ struct AskQuestionResponse {
     answers: Vec<String>,
 }
 
-pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
-    let (mut question_text, mut question_player, mut question_masked) = (String::new(), String::new(), false);
+pub async fn ask_top_question(lobby_id: &str) -> GameResult<()> {
+    let (mut queued_question_id, mut question_text, mut question_player, mut question_masked) = (0, String::new(), String::new(), false);
+    let mut question_queued_at = 0.0;
     let mut question_voters = Vec::new();
+    let mut question_bids = HashMap::new();
     let mut items = Vec::new();
     let mut is_quizmaster = false;
 
@@ -39,21 +47,40 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
         let question = lobby
             .questions_queue
             .iter()
-            .max_by_key(|question| question.voters.len())
-            .ok_or_else(|| anyhow!("No questions in queue"))?;
+            .max_by_key(|question| lobby.question_priority(question))
+            .ok_or(GameError::NoQuestionsQueued)?;
 
-        if question.voters.len() < lobby.settings.question_min_votes {
-            bail!("Question needs at least {} votes", lobby.settings.question_min_votes);
+        let required = lobby.question_threshold();
+        if lobby.question_priority(question) < required {
+            return Err(match lobby.settings.queue_mode {
+                QueueMode::Voting => GameError::NotEnoughVotes { required },
+                QueueMode::Auction => GameError::NotEnoughBids { required },
+            });
         }
 
+        queued_question_id = question.id;
         question_text.clone_from(&question.question);
         question_player.clone_from(&question.player);
         question_masked = question.masked;
+        question_queued_at = question.queued_at;
         question_voters.clone_from(&question.voters);
+        question_bids.clone_from(&question.bids);
         items.clone_from(&lobby.items);
 
         // Remove question from queue
-        lobby.questions_queue.retain(|q| q.question != question_text);
+        lobby.questions_queue.retain(|q| q.id != queued_question_id);
+
+        // Asking a question closes the auction, bids on the questions that lost are returned
+        if lobby.settings.queue_mode == QueueMode::Auction {
+            let losing_bids = lobby
+                .questions_queue
+                .iter_mut()
+                .map(|question| std::mem::take(&mut question.bids))
+                .collect::<Vec<_>>();
+            for bids in &losing_bids {
+                lobby.refund_bids(bids);
+            }
+        }
 
         // Reset queue waiting if needed
         if !lobby.questions_queue_active() {
@@ -73,11 +100,14 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
             .collect::<HashMap<usize, Answer>>();
         with_lobby(lobby_id, |lobby| {
             lobby.quizmaster_queue.push(QueuedQuestion {
+                id: queued_question_id,
                 question: question_text.clone(),
                 player: question_player.clone(),
                 masked: question_masked,
                 voters: question_voters,
+                bids: question_bids,
                 answers: answers_hashmap,
+                queued_at: lobby.elapsed_time,
             });
             Ok(())
         })?;
@@ -99,7 +129,7 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
             futures.push(future);
         }
 
-        let responses: Vec<Result<String>> = join_all(futures).await;
+        let responses: Vec<anyhow::Result<String>> = join_all(futures).await;
         for response in responses.into_iter().flatten() {
             if let Ok(validate_response) = serde_json::from_str::<AskQuestionResponse>(&response) {
                 let mut choices = Vec::new();
@@ -138,9 +168,26 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
         answers.push(most_common_answer);
     }
 
-    with_lobby(lobby_id, |lobby| {
+    let mut stalled_items = Vec::new();
+    let result = with_lobby(lobby_id, |lobby| {
+        // The game was paused while the AI answered, so the question goes back in the queue to be asked on resume
+        if lobby.state == LobbyState::Paused {
+            lobby.questions_queue.push(QueuedQuestion {
+                id: queued_question_id,
+                question: question_text.clone(),
+                player: question_player.clone(),
+                masked: question_masked,
+                voters: question_voters.clone(),
+                bids: question_bids.clone(),
+                answers: HashMap::new(),
+                queued_at: question_queued_at,
+            });
+            return Err(GameError::GamePaused);
+        }
         if answers.len() != lobby.items.len() {
-            bail!("Failed to get answers for question '{question_text}'");
+            return Err(GameError::AnswersUnavailable {
+                question: question_text.clone(),
+            });
         }
 
         let question_id = lobby.questions_counter;
@@ -151,7 +198,9 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
             id: question_id,
             text: question_text.clone(),
             masked: question_masked,
+            unmasked_by: Vec::new(),
         });
+        publish_old_masked_questions(lobby);
 
         // Ask question against each item
         let mut remove_items = Vec::new();
@@ -159,14 +208,16 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
             let answer = answers.get(index).unwrap_or(&Answer::Unknown);
             item.answers.insert(question_id, *answer);
 
-            // If item has 20 questions, remove the item
-            if item.answers.len() >= 20 {
+            // If item has run out of questions, remove the item
+            if item.answers.len() >= lobby.settings.questions_per_item {
                 remove_items.push(item.clone());
                 for player_n in lobby.players.values_mut() {
                     player_n.messages.push(PlayerMessage::ItemRemoved(item.id, item.name.clone()));
                 }
             }
         }
+        stalled_items = stalled_item_ids(lobby);
+        reward_informative_question(lobby, &question_player, &question_voters, &question_bids, &answers);
         if !remove_items.is_empty() {
             for item in &remove_items {
                 add_chat_message_to_lobby(
@@ -187,16 +238,22 @@ pub async fn ask_top_question(lobby_id: &str) -> Result<()> {
         }
         test_game_over(lobby);
         Ok(())
-    })
+    });
+    request_clues(lobby_id, stalled_items);
+    result
 }
 
-pub fn quizmaster_change_answer(lobby_id: &str, player_name: &str, question: &String, item_id: usize, new_answer: Answer) {
+pub fn quizmaster_change_answer(lobby_id: &str, player_name: &str, question_id: usize, item_id: usize, new_answer: Answer) {
     let result = with_lobby(lobby_id, |lobby| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        let player = lobby.players.get(player_name).ok_or_else(|| anyhow!("Player not found"))?;
-        ensure!(player.quizmaster, "Only quizmaster can use this");
+        lobby.ensure_playing()?;
+        let player = lobby.players.get(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if !player.quizmaster {
+            return Err(GameError::NotQuizmaster);
+        }
         for queued_question in &mut lobby.quizmaster_queue {
-            if question == &queued_question.question {
+            if queued_question.id == question_id {
                 for (id, answer) in &mut queued_question.answers {
                     if id == &item_id {
                         *answer = new_answer;
@@ -207,21 +264,29 @@ pub fn quizmaster_change_answer(lobby_id: &str, player_name: &str, question: &St
         Ok(())
     });
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("Change answer failed {error}"));
+        alert_popup(lobby_id, player_name, error);
     }
 }
 
-pub fn quizmaster_submit(lobby_id: &str, player_name: &str, question: &str) {
+pub fn quizmaster_submit(lobby_id: &str, player_name: &str, question_id: usize) {
+    let mut stalled_items = Vec::new();
     let result = with_lobby(lobby_id, |lobby| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        let player = lobby.players.get(player_name).ok_or_else(|| anyhow!("Player not found"))?;
-        ensure!(player.quizmaster, "Only quizmaster can use this");
+        lobby.ensure_playing()?;
+        if lobby.overtime {
+            return Err(GameError::TimeUp);
+        }
+        let player = lobby.players.get(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if !player.quizmaster {
+            return Err(GameError::NotQuizmaster);
+        }
 
         let question_index = lobby
             .quizmaster_queue
             .iter()
-            .position(|q| q.question == question)
-            .ok_or_else(|| anyhow!("Question not found"))?;
+            .position(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
         let question = lobby.quizmaster_queue.remove(question_index);
 
         let question_id = lobby.questions_counter;
@@ -231,16 +296,23 @@ pub fn quizmaster_submit(lobby_id: &str, player_name: &str, question: &str) {
             id: question_id,
             text: question.question.clone(),
             masked: question.masked,
+            unmasked_by: Vec::new(),
         });
+        publish_old_masked_questions(lobby);
 
+        let board_answers = lobby
+            .items
+            .iter()
+            .filter_map(|item| question.answers.get(&item.id).copied())
+            .collect::<Vec<_>>();
         let mut remove_items = Vec::new();
         for (item_id, answer) in question.answers {
             let item = lobby.items.iter_mut().find(|i| i.id == item_id);
             if let Some(item) = item {
                 item.answers.insert(question_id, answer);
 
-                // If item has 20 questions, remove the item
-                if item.answers.len() >= 20 {
+                // If item has run out of questions, remove the item
+                if item.answers.len() >= lobby.settings.questions_per_item {
                     remove_items.push(item.clone());
                     for player_n in lobby.players.values_mut() {
                         player_n.messages.push(PlayerMessage::ItemRemoved(item.id, item.name.clone()));
@@ -249,6 +321,8 @@ pub fn quizmaster_submit(lobby_id: &str, player_name: &str, question: &str) {
             }
         }
 
+        stalled_items = stalled_item_ids(lobby);
+        reward_informative_question(lobby, &question.player, &question.voters, &question.bids, &board_answers);
         if !remove_items.is_empty() {
             lobby.items.retain(|i| !remove_items.contains(i));
         }
@@ -263,22 +337,27 @@ pub fn quizmaster_submit(lobby_id: &str, player_name: &str, question: &str) {
 
         Ok(())
     });
+    request_clues(lobby_id, stalled_items);
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("Submission failed {error}"));
+        alert_popup(lobby_id, player_name, error);
     }
 }
 
-pub fn quizmaster_reject(lobby_id: &str, player_name: &str, question: &str) {
+pub fn quizmaster_reject(lobby_id: &str, player_name: &str, question_id: usize) {
     let result = with_lobby(lobby_id, |lobby| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        let player = lobby.players.get(player_name).ok_or_else(|| anyhow!("Player not found"))?;
-        ensure!(player.quizmaster, "Only quizmaster can use this");
+        lobby.ensure_playing()?;
+        let player = lobby.players.get(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if !player.quizmaster {
+            return Err(GameError::NotQuizmaster);
+        }
 
         let question_index = lobby
             .quizmaster_queue
             .iter()
-            .position(|q| q.question == question)
-            .ok_or_else(|| anyhow!("Question not found"))?;
+            .position(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
         let question = lobby.quizmaster_queue.remove(question_index);
 
         add_chat_message_to_lobby(
@@ -287,97 +366,472 @@ pub fn quizmaster_reject(lobby_id: &str, player_name: &str, question: &str) {
             &format!("Quizmaster has rejected question '{}'", question.question.clone()),
         );
 
-        // Refund the voters
-        for voter in question.voters {
-            if let Some(player) = lobby.players.get_mut(&voter) {
-                player.coins += 1;
-            }
-        }
+        // Refund the voters and bidders
+        lobby.refund_backers(&question);
         // Refund the question submitter and send them a message
+        let submit_question_cost = lobby.settings.submit_question_cost;
+        if let Some(coins) = lobby.coins_mut(&question.player) {
+            *coins += submit_question_cost;
+        }
         if let Some(player) = lobby.players.get_mut(&question.player) {
-            player.coins += lobby.settings.submit_question_cost;
             player.messages.push(PlayerMessage::QuestionRejected(question.question));
         }
 
         Ok(())
     });
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("Rejection failed {error}"));
+        alert_popup(lobby_id, player_name, error);
     }
 }
 
 pub fn player_guess_item(lobby_id: &str, player_name: &str, item_choice: usize, guess: &str) {
     let result = with_lobby(lobby_id, |lobby| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        let player = lobby
-            .players
-            .get_mut(player_name)
-            .ok_or_else(|| anyhow!("Player '{player_name}' not found"))?;
-        ensure!(!player.quizmaster, "Quizmaster cannot engage");
-        ensure!(player.coins >= lobby.settings.guess_item_cost, "Insufficient coins to guess");
+        lobby.ensure_playing()?;
+        let available = lobby.coins(player_name);
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        if available < lobby.settings.guess_item_cost {
+            return Err(GameError::InsufficientCoins {
+                required: lobby.settings.guess_item_cost,
+                available,
+            });
+        }
 
         let item_index = lobby
             .items
             .iter()
             .position(|i| i.id == item_choice)
-            .ok_or_else(|| anyhow!("Item not found"))?;
+            .ok_or(GameError::ItemNotFound { item_id: item_choice })?;
         let item = &lobby.items[item_index];
 
-        player.coins -= lobby.settings.guess_item_cost;
-        if item.name.eq_ignore_ascii_case(guess) {
+        let guess_correct = item.name.eq_ignore_ascii_case(guess);
+        if guess_correct {
+            // Every hint bought on the item makes it worth less, whoever bought it
+            player.award_points(
+                lobby
+                    .settings
+                    .guess_score(item.answers.len())
+                    .saturating_sub(item.hints.len() * lobby.settings.hint_score_penalty),
+            );
+        } else {
+            player.messages.push(PlayerMessage::GuessIncorrect);
+        }
+        let guess_item_cost = lobby.settings.guess_item_cost;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins -= guess_item_cost;
+        }
+        let item = &lobby.items[item_index];
+        if guess_correct {
             // Correct guess
-            player.score += 20 - item.answers.len();
-
             for p in lobby.players.values_mut() {
                 p.messages
                     .push(PlayerMessage::ItemGuessed(player_name.to_owned(), item.id, item.name.clone()));
             }
 
             lobby.items.remove(item_index);
-            add_chat_message_to_lobby(
-                lobby,
-                "SYSTEM",
-                &format!("'{player_name}' guessed item {item_choice} as '{guess}'",),
-            );
+            add_chat_message_to_lobby(lobby, "SYSTEM", &format!("'{player_name}' guessed item {item_choice} as '{guess}'"));
             test_game_over(lobby);
 
             Ok(())
         } else {
             // Incorrect guess
-            player.messages.push(PlayerMessage::GuessIncorrect);
             add_chat_message_to_lobby(
                 lobby,
                 "SYSTEM",
-                &format!("'{player_name}' incorrectly guessed '{guess}' for item {item_choice}",),
+                &format!("'{player_name}' incorrectly guessed '{guess}' for item {item_choice}"),
             );
-            bail!("Incorrect guess");
+            Err(GameError::IncorrectGuess)
         }
     });
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("Guess rejected {error}"));
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// How evenly a question's answers split the items, 0 when every item got the same answer and 1 when the answers given are evenly shared
+pub fn information_gain(answers: &[Answer]) -> f64 {
+    let total = answers.len();
+    let mut answer_counts: HashMap<Answer, usize> = HashMap::new();
+    for answer in answers {
+        *answer_counts.entry(*answer).or_insert(0) += 1;
+    }
+    if answer_counts.len() < 2 {
+        return 0.0;
+    }
+    let entropy = answer_counts
+        .values()
+        .map(|count| {
+            let share = *count as f64 / total as f64;
+            -share * share.log2()
+        })
+        .sum::<f64>();
+    let max_entropy = (answer_counts.len() as f64).log2();
+    entropy / max_entropy
+}
+
+// How many of the answered items fall outside the largest group sharing one answer, the items the question tells apart from the rest
+pub fn items_separated(answers: &[Answer]) -> usize {
+    let mut answer_counts: HashMap<Answer, usize> = HashMap::new();
+    for answer in answers {
+        *answer_counts.entry(*answer).or_insert(0) += 1;
+    }
+    answers.len() - answer_counts.values().max().copied().unwrap_or_default()
+}
+
+// For each item, how many other items gave the same answer to every question both were asked, 0 once its answers are unique
+pub fn item_ambiguity(items: &[Item]) -> Vec<usize> {
+    items
+        .iter()
+        .map(|item| {
+            items
+                .iter()
+                .filter(|other| {
+                    other.id != item.id
+                        && item
+                            .answers
+                            .iter()
+                            .all(|(question_id, answer)| other.answers.get(question_id).is_none_or(|other_answer| other_answer == answer))
+                })
+                .count()
+        })
+        .collect()
+}
+
+// Pays the asker, and the voters or bidders if enabled, for a question in proportion to how well it split the items on the board
+fn reward_informative_question(lobby: &mut Lobby, asker: &str, voters: &[String], bids: &HashMap<String, usize>, answers: &[Answer]) {
+    let reward = (information_gain(answers) * lobby.settings.informative_reward as f64).round() as usize;
+    if reward == 0 {
+        return;
+    }
+    let mut recipients = vec![asker.to_owned()];
+    if lobby.settings.reward_voters {
+        recipients.extend(voters.iter().chain(bids.keys()).cloned());
+    }
+    recipients.sort();
+    recipients.dedup();
+
+    let reward_kind = lobby.settings.informative_reward_kind;
+    for recipient in &recipients {
+        match reward_kind {
+            RewardKind::Coins => {
+                if let Some(coins) = lobby.coins_mut(recipient) {
+                    *coins += reward;
+                }
+            }
+            RewardKind::Score => {
+                if let Some(player) = lobby.players.get_mut(recipient) {
+                    player.award_points(reward);
+                }
+            }
+        }
+    }
+    let reward_text = match reward_kind {
+        RewardKind::Coins => format!("{reward}🪙"),
+        RewardKind::Score => format!("{reward} points"),
+    };
+    add_chat_message_to_lobby(
+        lobby,
+        "SYSTEM",
+        &format!(
+            "'{asker}' asked an informative question, {} earned {reward_text}",
+            recipients.join(", ")
+        ),
+    );
+}
+
+#[derive(Deserialize)]
+struct ClueResponse {
+    clue: String,
+}
+
+// Items that just reached the clue threshold without being guessed, items about to run out of questions are skipped
+fn stalled_item_ids(lobby: &Lobby) -> Vec<usize> {
+    let threshold = lobby.settings.ai_clue_after_questions;
+    if threshold == 0 || threshold >= lobby.settings.questions_per_item {
+        return Vec::new();
+    }
+    lobby
+        .items
+        .iter()
+        .filter(|item| item.clue.is_none() && item.answers.len() == threshold)
+        .map(|item| item.id)
+        .collect()
+}
+
+fn request_clues(lobby_id: &str, item_ids: Vec<usize>) {
+    for item_id in item_ids {
+        let lobby_id = lobby_id.to_owned();
+        tokio::spawn(async move {
+            let _result = generate_clue(&lobby_id, item_id).await;
+        });
+    }
+}
+
+// A clue that names the item, or any real word of it, gives the answer away
+fn clue_is_valid(clue: &str, item_name: &str) -> bool {
+    let clue = clue.to_lowercase();
+    let item_name = item_name.to_lowercase();
+    !clue.trim().is_empty()
+        && clue.len() <= 100
+        && !clue.contains(&item_name)
+        && item_name
+            .split_whitespace()
+            .filter(|word| word.len() >= 3)
+            .all(|word| !clue.contains(word))
+}
+
+async fn generate_clue(lobby_id: &str, item_id: usize) -> GameResult<()> {
+    let item_name = with_lobby(lobby_id, |lobby| {
+        lobby
+            .items
+            .iter()
+            .find(|item| item.id == item_id)
+            .map(|item| item.name.clone())
+            .ok_or(GameError::ItemNotFound { item_id })
+    })?;
+
+    let prompt = format!("u:Give a short vague clue for the item '{item_name}' in a 20 questions game, such as its category or a cryptic hint, never use the item name or any part of it, return compact one line JSON with key clue, British English");
+    let mut clue = None;
+    for _ in 0..3 {
+        let Ok(response) = query_ai(&prompt, 60, 1.0, true).await else {
+            continue;
+        };
+        match serde_json::from_str::<ClueResponse>(&response) {
+            Ok(response) if clue_is_valid(&response.clue, &item_name) => {
+                clue = Some(response.clue.trim().to_owned());
+                break;
+            }
+            _ => println!("Rejected clue response {response}"),
+        }
+    }
+    let Some(clue) = clue else {
+        println!("Failed to generate a clue for item {item_id} in lobby '{lobby_id}'");
+        return Ok(());
+    };
+
+    with_lobby(lobby_id, |lobby| {
+        // The item may have been guessed while the clue was being generated
+        if let Some(item) = lobby.items.iter_mut().find(|item| item.id == item_id) {
+            item.clue = Some(clue.clone());
+            add_chat_message_to_lobby(lobby, "SYSTEM", &format!("Clue for item {item_id}: {clue}"));
+        }
+        Ok(())
+    })
+}
+
+// Lets a player see a masked question on the board, part of what they pay goes to the asker
+pub fn unmask_question(lobby_id: &str, player_name: &str, question_id: usize) {
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let available = lobby.coins(player_name);
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        let question = lobby
+            .questions
+            .iter_mut()
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+        if question.visible_to(player_name, false) {
+            return Err(GameError::QuestionAlreadyVisible);
+        }
+        if available < lobby.settings.unmask_cost {
+            return Err(GameError::InsufficientCoins {
+                required: lobby.settings.unmask_cost,
+                available,
+            });
+        }
+
+        question.unmasked_by.push(player_name.to_owned());
+        let asker = question.player.clone();
+        let unmask_cost = lobby.settings.unmask_cost;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins -= unmask_cost;
+        }
+        let asker_share = unmask_cost * lobby.settings.unmask_asker_percent / 100;
+        if let Some(coins) = lobby.coins_mut(&asker) {
+            *coins += asker_share;
+        }
+        add_chat_message_to_lobby(
+            lobby,
+            "SYSTEM",
+            &format!("'{player_name}' paid to unmask a question from '{asker}'"),
+        );
+        Ok(())
+    });
+    if let Err(error) = result {
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// Masked questions become public once enough further questions have been asked after them
+fn publish_old_masked_questions(lobby: &mut Lobby) {
+    let after_questions = lobby.settings.unmask_after_questions;
+    if after_questions == 0 {
+        return;
+    }
+    let latest_id = lobby.questions_counter.saturating_sub(1);
+    let mut published = Vec::new();
+    for question in &mut lobby.questions {
+        if question.masked && latest_id - question.id >= after_questions {
+            question.masked = false;
+            published.push(format!(
+                "A masked question from '{}' is now public: {}",
+                question.player, question.text
+            ));
+        }
+    }
+    for message in published {
+        add_chat_message_to_lobby(lobby, "SYSTEM", &message);
+    }
+}
+
+// Sells a clue about one item, with private hints each player can buy their own copy of a clue
+pub fn buy_hint(lobby_id: &str, player_name: &str, item_id: usize, kind: HintKind) {
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let available = lobby.coins(player_name);
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        if available < lobby.settings.hint_cost {
+            return Err(GameError::InsufficientCoins {
+                required: lobby.settings.hint_cost,
+                available,
+            });
+        }
+        let item = lobby
+            .items
+            .iter_mut()
+            .find(|i| i.id == item_id)
+            .ok_or(GameError::ItemNotFound { item_id })?;
+
+        let known_hints = item
+            .hints
+            .iter()
+            .filter(|hint| hint.visible_to(player_name, false))
+            .collect::<Vec<_>>();
+        if kind != HintKind::Letter && known_hints.iter().any(|hint| hint.kind == kind) {
+            return Err(GameError::HintAlreadyKnown);
+        }
+        let chars = item.name.chars().collect::<Vec<_>>();
+        let (text, position) = match kind {
+            HintKind::Length => {
+                let word_lengths = item
+                    .name
+                    .split_whitespace()
+                    .map(|word| word.chars().count().to_string())
+                    .collect::<Vec<_>>();
+                let text = if word_lengths.len() > 1 {
+                    format!("Word lengths {}", word_lengths.join(", "))
+                } else {
+                    format!("{} letters", chars.len())
+                };
+                (text, None)
+            }
+            HintKind::FirstLetter => (format!("Starts with '{}'", chars[0].to_ascii_uppercase()), Some(0)),
+            HintKind::Letter => {
+                let revealed = known_hints.iter().filter_map(|hint| hint.position).collect::<Vec<_>>();
+                let hidden = (0..chars.len())
+                    .filter(|position| chars[*position].is_alphanumeric() && !revealed.contains(position))
+                    .collect::<Vec<_>>();
+                let position = *hidden.choose(&mut thread_rng()).ok_or(GameError::NoHintsLeft)?;
+                (
+                    format!("Letter {} is '{}'", position + 1, chars[position].to_ascii_lowercase()),
+                    Some(position),
+                )
+            }
+        };
+
+        item.hints.push(Hint {
+            kind,
+            text: text.clone(),
+            purchaser: player_name.to_owned(),
+            public: lobby.settings.public_hints,
+            position,
+        });
+        let hint_cost = lobby.settings.hint_cost;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins -= hint_cost;
+        }
+        if lobby.settings.public_hints {
+            add_chat_message_to_lobby(
+                lobby,
+                "SYSTEM",
+                &format!("'{player_name}' bought a hint for item {item_id}: {text}"),
+            );
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        alert_popup(lobby_id, player_name, error);
     }
 }
 
 pub fn test_game_over(lobby: &mut Lobby) {
-    if lobby.items.is_empty() && !lobby.items_queue.is_empty() {
+    if lobby.items.is_empty() && !lobby.items_queue.is_empty() && !lobby.overtime {
         add_item_to_lobby(lobby);
     }
     if lobby.state == LobbyState::Play && lobby.items.is_empty() {
-        lobby.state = LobbyState::Ended;
         lobby.elapsed_time = 0.0;
+        lobby.overtime = false;
 
-        // Find winner, player with max score, or if tied multiple players, or if 0 score no winner
+        for player in lobby.players.values_mut().filter(|player| !player.spectator) {
+            player.round_scores.push(std::mem::take(&mut player.round_score));
+        }
+        if lobby.round < lobby.settings.rounds {
+            start_next_round(lobby);
+            return;
+        }
+        lobby.state = LobbyState::Ended;
+
+        // Find winner, player or team with max score, or if tied multiple, or if 0 score no winner
+        let contenders = if lobby.settings.team_mode {
+            lobby
+                .settings
+                .teams
+                .iter()
+                .map(|team| format!("Team {team}"))
+                .zip(lobby.team_scores())
+                .collect::<Vec<_>>()
+        } else {
+            lobby
+                .players
+                .values()
+                .filter(|player| !player.spectator)
+                .map(|player| (player.name.clone(), player.score))
+                .collect()
+        };
         let mut max_score = 0;
         let mut winners = Vec::new();
-        for player in lobby.players.values() {
-            match player.score.cmp(&max_score) {
+        for (name, score) in contenders {
+            match score.cmp(&max_score) {
                 Ordering::Greater => {
-                    max_score = player.score;
+                    max_score = score;
                     winners.clear();
-                    winners.push(player.name.clone());
+                    winners.push(name);
                 }
                 Ordering::Equal => {
-                    winners.push(player.name.clone());
+                    winners.push(name);
                 }
                 Ordering::Less => {}
             }
@@ -385,10 +839,13 @@ pub fn test_game_over(lobby: &mut Lobby) {
         if max_score == 0 {
             winners.clear();
         }
+        record_finished_game(lobby, winners.clone());
         let win_message = if winners.len() > 1 {
             format!("The tied winners are {}!", winners.join(", "))
         } else if winners.is_empty() {
             String::from("The game has ended with no winner!")
+        } else if lobby.settings.rounds > 1 {
+            format!("{} wins the series!", winners[0])
         } else {
             format!("The winner is {}!", winners[0])
         };
@@ -398,3 +855,361 @@ pub fn test_game_over(lobby: &mut Lobby) {
         }
     }
 }
+
+// Called once the time limit runs out, no more questions are asked but players can still guess until the window closes
+pub fn start_overtime(lobby: &mut Lobby) {
+    lobby.overtime = true;
+    let message = format!(
+        "Time is up! No more questions, you have {} seconds for final guesses",
+        lobby.settings.final_guess_seconds
+    );
+    add_chat_message_to_lobby(lobby, "SYSTEM", &message);
+    for player in lobby.players.values_mut() {
+        player.messages.push(PlayerMessage::TimeUp(message.clone()));
+    }
+}
+
+// Ends the round once the final guessing window closes, items still on the board or in the queue are not scored
+pub fn reveal_remaining_items(lobby: &mut Lobby) {
+    let remaining = lobby
+        .items
+        .drain(..)
+        .map(|item| format!("item {} was '{}'", item.id, item.name))
+        .collect::<Vec<_>>();
+    lobby.items_queue.clear();
+    if !remaining.is_empty() {
+        add_chat_message_to_lobby(lobby, "SYSTEM", &format!("The unguessed items: {}", remaining.join(", ")));
+    }
+    test_game_over(lobby);
+}
+
+// Clears the board for the next round of a series, the lobby loop then generates new items and starts it
+// In quizmaster games the role passes to the next player in join order
+fn start_next_round(lobby: &mut Lobby) {
+    lobby.round += 1;
+    lobby.state = LobbyState::Starting;
+    lobby.questions_queue.clear();
+    lobby.quizmaster_queue.clear();
+    lobby.items.clear();
+    lobby.items_queue.clear();
+    lobby.questions.clear();
+    lobby.coins_countdown = 0.0;
+    lobby.questions_queue_countdown = 0.0;
+    lobby.items_counter = 0;
+    lobby.questions_counter = 0;
+
+    if lobby.settings.player_controlled {
+        let mut candidates = lobby.players.values().filter(|player| !player.spectator).collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.joined_at.total_cmp(&b.joined_at));
+        let current = candidates.iter().position(|player| player.quizmaster);
+        let next_quizmaster = current
+            .map_or_else(|| candidates.first(), |index| candidates.get((index + 1) % candidates.len()))
+            .map(|player| player.name.clone());
+        for player in lobby.players.values_mut() {
+            player.quizmaster = next_quizmaster.as_ref() == Some(&player.name);
+        }
+        if let Some(next_quizmaster) = next_quizmaster {
+            add_chat_message_to_lobby(
+                lobby,
+                "SYSTEM",
+                &format!("'{next_quizmaster}' is the quizmaster for the next round"),
+            );
+        }
+    }
+
+    let round_message = format!("Round {} of {} is starting", lobby.round, lobby.settings.rounds);
+    add_chat_message_to_lobby(lobby, "SYSTEM", &round_message);
+    for player in lobby.players.values_mut() {
+        player.messages.push(PlayerMessage::RoundOver(round_message.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{
+        question_queue::{bid_question, convert_score},
+        testing::{answer_with, block_on, TestLobby},
+    };
+
+    #[test]
+    fn information_gain_needs_two_answers() {
+        assert!(information_gain(&[]).abs() < f64::EPSILON);
+        assert!(information_gain(&[Answer::Yes]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn information_gain_of_identical_answers_is_zero() {
+        assert!(information_gain(&[Answer::No; 5]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn information_gain_of_even_splits() {
+        assert!((information_gain(&[Answer::Yes, Answer::No]) - 1.0).abs() < 1e-9);
+        assert!((information_gain(&[Answer::Yes, Answer::No, Answer::Maybe, Answer::Unknown]) - 1.0).abs() < 1e-9);
+        assert!((information_gain(&[Answer::Yes, Answer::Yes, Answer::No, Answer::No]) - 1.0).abs() < 1e-9);
+        assert!((information_gain(&[Answer::Yes, Answer::Yes, Answer::Yes, Answer::No]) - 0.811_278).abs() < 1e-6);
+    }
+
+    #[test]
+    fn items_separated_counts_items_outside_the_largest_group() {
+        assert_eq!(items_separated(&[]), 0);
+        assert_eq!(items_separated(&[Answer::Yes; 4]), 0);
+        assert_eq!(items_separated(&[Answer::Yes, Answer::Yes, Answer::No, Answer::Maybe]), 2);
+    }
+
+    fn item(id: usize, answers: &[(usize, Answer)]) -> Item {
+        Item {
+            name: format!("Item {id}"),
+            id,
+            answers: answers.iter().copied().collect(),
+            hints: Vec::new(),
+            clue: None,
+        }
+    }
+
+    #[test]
+    fn item_ambiguity_of_fewer_than_two_items() {
+        assert!(item_ambiguity(&[]).is_empty());
+        assert_eq!(item_ambiguity(&[item(1, &[(1, Answer::Yes)])]), vec![0]);
+    }
+
+    #[test]
+    fn item_ambiguity_when_all_answers_match() {
+        let items = [
+            item(1, &[(1, Answer::Yes)]),
+            item(2, &[(1, Answer::Yes)]),
+            item(3, &[(1, Answer::Yes)]),
+        ];
+        assert_eq!(item_ambiguity(&items), vec![2, 2, 2]);
+    }
+
+    #[test]
+    fn item_ambiguity_ignores_unanswered_questions() {
+        // Item 3 was added after question 1 was asked, so only question 2 is compared
+        let items = [
+            item(1, &[(1, Answer::Yes), (2, Answer::No)]),
+            item(2, &[(1, Answer::No), (2, Answer::No)]),
+            item(3, &[(2, Answer::No)]),
+        ];
+        assert_eq!(item_ambiguity(&items), vec![1, 1, 2]);
+    }
+
+    #[test]
+    fn hints_cost_coins_and_points() {
+        let test = TestLobby::new("hintcost", &["alice", "bob"]);
+        test.with(|lobby| {
+            lobby.settings.hint_cost = 2;
+            lobby.settings.hint_score_penalty = 3;
+            lobby.settings.public_hints = false;
+        });
+        test.start();
+
+        buy_hint("hintcost", "alice", 1, HintKind::Length);
+        assert_eq!(test.coins("alice"), 6);
+        let hint_visibility = test.with(|lobby| {
+            let hint = &lobby.items[0].hints[0];
+            (hint.text.clone(), hint.visible_to("alice", false), hint.visible_to("bob", false))
+        });
+        assert_eq!(hint_visibility, ("5 letters".to_owned(), true, false));
+
+        // The same kind of hint can't be bought twice, and a failed purchase costs nothing
+        buy_hint("hintcost", "alice", 1, HintKind::Length);
+        assert_eq!(test.coins("alice"), 6);
+
+        // Any hint on the item lowers its score, even one the guesser didn't buy
+        player_guess_item("hintcost", "bob", 1, "apple");
+        let bob_score = test.with(|lobby| lobby.players["bob"].score);
+        assert_eq!(bob_score, test.with(|lobby| lobby.settings.guess_score(0)) - 3);
+    }
+
+    #[test]
+    fn hints_need_enough_coins() {
+        let test = TestLobby::new("hintpoor", &["alice"]);
+        test.with(|lobby| lobby.settings.hint_cost = 9);
+        test.start();
+
+        buy_hint("hintpoor", "alice", 1, HintKind::FirstLetter);
+        assert_eq!(test.coins("alice"), 8);
+        assert!(test.with(|lobby| lobby.items[0].hints.is_empty()));
+    }
+
+    #[test]
+    fn stalled_items_get_a_clue() {
+        let test = TestLobby::new("cluestall", &["alice"]);
+        test.with(|lobby| {
+            lobby.settings.ai_clue_after_questions = 1;
+            lobby.settings.question_min_votes = 0;
+        });
+        test.start();
+        answer_with(|prompt| {
+            let response = if prompt.contains("key clue") {
+                r#"{"clue": "Not a yellow banana"}"#
+            } else {
+                r#"{"answers": ["yes", "no"]}"#
+            };
+            Ok(response.to_owned())
+        });
+        test.queue_question("alice", "Is it red?", &[]);
+
+        block_on(async {
+            ask_top_question("cluestall").await.unwrap();
+            // Clues are generated in the background once the question is recorded
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        });
+        // The clue gives Banana away, so only Apple keeps it
+        let clues = test.with(|lobby| lobby.items.iter().map(|item| item.clue.clone()).collect::<Vec<_>>());
+        assert_eq!(clues, vec![Some("Not a yellow banana".to_owned()), None]);
+    }
+
+    fn ask_masked(lobby: &mut Lobby, player_name: &str) -> usize {
+        let question_id = lobby.questions_counter;
+        lobby.questions_counter += 1;
+        lobby.questions.push(Question {
+            player: player_name.to_owned(),
+            id: question_id,
+            text: format!("Question {question_id}?"),
+            masked: true,
+            unmasked_by: Vec::new(),
+        });
+        question_id
+    }
+
+    #[test]
+    fn unmasking_pays_the_asker() {
+        let test = TestLobby::new("unmaskpay", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.unmask_cost = 4;
+            lobby.settings.unmask_asker_percent = 50;
+        });
+        test.start();
+        let question_id = test.with(|lobby| ask_masked(lobby, "bob"));
+
+        unmask_question("unmaskpay", "alice", question_id);
+        assert_eq!((test.coins("alice"), test.coins("bob")), (4, 10));
+        let visible_to = test.with(|lobby| ["alice", "bob", "carol"].map(|player_name| lobby.questions[0].visible_to(player_name, false)));
+        assert_eq!(visible_to, [true, true, false]);
+
+        // Paying again for a question already seen is refused
+        unmask_question("unmaskpay", "alice", question_id);
+        assert_eq!(test.coins("alice"), 4);
+    }
+
+    #[test]
+    fn masked_questions_are_published_after_enough_questions() {
+        let test = TestLobby::new("unmaskold", &["alice"]);
+        test.with(|lobby| lobby.settings.unmask_after_questions = 2);
+        test.start();
+
+        let masked = test.with(|lobby| {
+            ask_masked(lobby, "alice");
+            ask_masked(lobby, "alice");
+            publish_old_masked_questions(lobby);
+            let before = lobby.questions.iter().map(|question| question.masked).collect::<Vec<_>>();
+            ask_masked(lobby, "alice");
+            publish_old_masked_questions(lobby);
+            let after = lobby.questions.iter().map(|question| question.masked).collect::<Vec<_>>();
+            (before, after)
+        });
+        assert_eq!(masked, (vec![true, true], vec![false, true, true]));
+
+        // With publishing turned off masked questions stay hidden for good
+        let still_masked = test.with(|lobby| {
+            lobby.settings.unmask_after_questions = 0;
+            ask_masked(lobby, "alice");
+            ask_masked(lobby, "alice");
+            publish_old_masked_questions(lobby);
+            lobby.questions[1].masked
+        });
+        assert!(still_masked);
+    }
+
+    #[test]
+    fn round_scores_keep_points_converted_to_coins() {
+        let test = TestLobby::new("roundconvert", &["alice", "bob"]);
+        test.start();
+        test.with(|lobby| lobby.items_queue.clear());
+        let points = test.with(|lobby| lobby.settings.guess_score(0));
+
+        player_guess_item("roundconvert", "bob", 1, "apple");
+        for _ in 0..points - 5 {
+            convert_score("roundconvert", "bob");
+        }
+        player_guess_item("roundconvert", "bob", 2, "banana");
+
+        let bob = test.with(|lobby| lobby.players["bob"].clone());
+        assert!(test.with(|lobby| lobby.state == LobbyState::Ended));
+        assert_eq!((bob.score, bob.round_scores), (points + 5, vec![points * 2]));
+    }
+
+    #[test]
+    fn questions_answered_during_a_pause_are_queued_again() {
+        let test = TestLobby::new("askpause", &["alice"]);
+        test.with(|lobby| lobby.settings.question_min_votes = 0);
+        test.start();
+        let question_id = test.queue_question("alice", "Is it red?", &[]);
+        // The host pauses while the AI is still answering
+        answer_with(|_| {
+            with_lobby("askpause", |lobby| {
+                lobby.state = LobbyState::Paused;
+                Ok(())
+            })?;
+            Ok(r#"{"answers": ["yes", "no"]}"#.to_owned())
+        });
+
+        assert!(matches!(block_on(ask_top_question("askpause")), Err(GameError::GamePaused)));
+        let (queued, asked) = test.with(|lobby| {
+            (
+                lobby.questions_queue.iter().map(|q| q.id).collect::<Vec<_>>(),
+                lobby.questions.len(),
+            )
+        });
+        assert_eq!((queued, asked), (vec![question_id], 0));
+    }
+
+    #[test]
+    fn auctions_refund_the_losing_bids() {
+        let test = TestLobby::new("auctionask", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.queue_mode = QueueMode::Auction;
+            lobby.settings.min_total_bid = 5;
+        });
+        test.start();
+        let winner = test.queue_question("alice", "Is it red?", &[]);
+        let loser = test.queue_question("alice", "Is it round?", &[]);
+        bid_question("auctionask", "bob", winner, 3);
+        bid_question("auctionask", "carol", loser, 2);
+        assert!(matches!(
+            block_on(ask_top_question("auctionask")),
+            Err(GameError::NotEnoughBids { required: 5 })
+        ));
+
+        bid_question("auctionask", "bob", winner, 2);
+        answer_with(|_| Ok(r#"{"answers": ["yes", "no"]}"#.to_owned()));
+        block_on(ask_top_question("auctionask")).unwrap();
+        let queued = test.with(|lobby| lobby.questions_queue.iter().map(|q| (q.id, q.bids.len())).collect::<Vec<_>>());
+        assert_eq!(queued, vec![(loser, 0)]);
+        assert_eq!((test.coins("bob"), test.coins("carol")), (3, 8));
+    }
+
+    #[test]
+    fn bidders_share_the_informative_reward() {
+        let test = TestLobby::new("rewardbids", &["alice", "bob", "carol"]);
+        test.with(|lobby| {
+            lobby.settings.queue_mode = QueueMode::Auction;
+            lobby.settings.min_total_bid = 1;
+            lobby.settings.informative_reward = 10;
+            lobby.settings.reward_voters = true;
+        });
+        test.start();
+        let question_id = test.queue_question("alice", "Is it red?", &[]);
+        bid_question("rewardbids", "bob", question_id, 1);
+
+        // An even yes and no split earns the full reward
+        answer_with(|_| Ok(r#"{"answers": ["yes", "no"]}"#.to_owned()));
+        block_on(ask_top_question("rewardbids")).unwrap();
+        assert_eq!([test.coins("alice"), test.coins("bob"), test.coins("carol")], [18, 17, 8]);
+    }
+}
@@ -58,7 +58,7 @@ struct Usage {
     total_tokens: usize,
 }
 
-pub async fn query_ai(prompt: &str, max_tokens: usize, temperature: f32, use_json: bool) -> Result<String> {
+pub async fn query_openai(prompt: &str, max_tokens: usize, temperature: f32, use_json: bool) -> Result<String> {
     let api_key = env::var("OPENAI_API_KEY").context("No OPENAI_API_KEY found in environment")?;
 
     let messages: Result<Vec<Message>> = prompt
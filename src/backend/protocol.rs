@@ -0,0 +1,336 @@
+use crate::backend::{
+    error::GameError, get_current_time, Answer, BanLength, ChatMessage, HintKind, Lobby, LobbySettings, LobbyState, PlayerMessage,
+    PlayerReduced, QueuedQuestion,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Bump whenever commands or events change in a way existing clients can't handle
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Sent by clients as JSON text frames, e.g. {"type": "vote_question", "question_id": 3}
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientCommand {
+    Connect {
+        lobby_id: String,
+        player_name: String,
+        #[serde(default)]
+        credential: String,
+        #[serde(default)]
+        previous_session_token: String,
+        #[serde(default)]
+        spectator: bool,
+    },
+    Reconnect {
+        lobby_id: String,
+        player_name: String,
+        session_token: String,
+    },
+    Disconnect,
+    StartLobby,
+    PauseLobby,
+    ResumeLobby,
+    Rematch,
+    TransferKeyPlayer {
+        new_key_player: String,
+    },
+    KickPlayer {
+        player_name: String,
+        #[serde(default)]
+        reason: String,
+        #[serde(default)]
+        ban_length: BanLength,
+    },
+    UnbanPlayer {
+        player_name: String,
+    },
+    JoinTeam {
+        team: usize,
+    },
+    Chat {
+        message: String,
+        #[serde(default)]
+        team_only: bool,
+    },
+    SubmitQuestion {
+        question: String,
+        masked: bool,
+    },
+    EditQuestion {
+        question_id: usize,
+        question: String,
+    },
+    WithdrawQuestion {
+        question_id: usize,
+    },
+    VoteQuestion {
+        question_id: usize,
+    },
+    UnvoteQuestion {
+        question_id: usize,
+    },
+    BidQuestion {
+        question_id: usize,
+        amount: usize,
+    },
+    GuessItem {
+        item_id: usize,
+        guess: String,
+    },
+    BuyHint {
+        item_id: usize,
+        kind: HintKind,
+    },
+    UnmaskQuestion {
+        question_id: usize,
+    },
+    ConvertScore,
+    QuizmasterChangeAnswer {
+        question_id: usize,
+        item_id: usize,
+        answer: Answer,
+    },
+    QuizmasterSubmit {
+        question_id: usize,
+    },
+    QuizmasterReject {
+        question_id: usize,
+    },
+}
+
+// Sent to clients as JSON text frames, e.g. {"type": "message", "data": {"type": "coin_given"}}
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Hello {
+        version: u32,
+    },
+    Connected {
+        lobby_id: String,
+        player_name: String,
+        session_token: String,
+    },
+    Disconnected,
+    Error(GameError),
+    InvalidCommand {
+        reason: String,
+    },
+    Message(PlayerMessage),
+    State(Box<LobbyView>),
+    StateDiff(Box<LobbyViewDiff>),
+}
+
+// The lobby as one player is allowed to see it, item names and other players masked questions are hidden
+#[derive(Clone, PartialEq, Serialize)]
+pub struct LobbyView {
+    pub id: String,
+    pub state: LobbyState,
+    pub round: usize,
+    pub elapsed_time: usize,
+    pub time_left: Option<usize>,
+    pub overtime: bool,
+    pub key_player: String,
+    pub invite_code: String,
+    pub settings: LobbySettings,
+    pub players: Vec<PlayerReduced>,
+    pub bans: Vec<BanView>,
+    pub chat_messages: Vec<ChatMessage>,
+    pub questions_queue: Vec<QueuedQuestionView>,
+    pub questions_queue_active: bool,
+    pub questions_queue_countdown: usize,
+    pub quizmaster_queue: Vec<QueuedQuestionView>,
+    pub items: Vec<ItemView>,
+    pub questions: Vec<QuestionView>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct BanView {
+    pub player_name: String,
+    pub reason: String,
+    pub minutes_left: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct QueuedQuestionView {
+    pub id: usize,
+    pub player: String,
+    pub question: Option<String>,
+    pub masked: bool,
+    pub votes: usize,
+    // Bids are sealed, so players only see their own
+    pub my_bid: usize,
+    pub expires_in: Option<usize>,
+    pub answers: HashMap<usize, Answer>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct ItemView {
+    pub id: usize,
+    pub name: Option<String>,
+    pub answers: HashMap<usize, Answer>,
+    pub hints: Vec<String>,
+    pub clue: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct QuestionView {
+    pub id: usize,
+    pub player: String,
+    pub text: Option<String>,
+    pub masked: bool,
+}
+
+impl LobbyView {
+    pub fn new(lobby: &Lobby, player_name: &str) -> Self {
+        let is_quizmaster = lobby.players.get(player_name).is_some_and(|p| p.quizmaster);
+        let is_key_player = lobby.key_player == player_name;
+        let can_see = |asker: &str, masked: bool| !masked || asker == player_name || is_quizmaster;
+
+        let mut players = lobby
+            .players
+            .values()
+            .map(|player| player.reduce(lobby, player_name))
+            .collect::<Vec<_>>();
+        players.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let queued_view = |question: &QueuedQuestion| QueuedQuestionView {
+            id: question.id,
+            player: question.player.clone(),
+            question: can_see(&question.player, question.masked).then(|| question.question.clone()),
+            masked: question.masked,
+            votes: question.voters.len(),
+            my_bid: question.bids.get(player_name).copied().unwrap_or_default(),
+            expires_in: lobby.question_expires_in(question).map(|expires_in| expires_in.ceil() as usize),
+            answers: question.answers.clone(),
+        };
+
+        Self {
+            id: lobby.id.clone(),
+            state: lobby.state.clone(),
+            round: lobby.round,
+            elapsed_time: lobby.elapsed_time.round() as usize,
+            time_left: lobby.time_left().map(|time_left| time_left.ceil() as usize),
+            overtime: lobby.overtime,
+            key_player: lobby.key_player.clone(),
+            invite_code: lobby.invite_code.clone(),
+            settings: lobby.settings.clone(),
+            players,
+            bans: if is_key_player {
+                lobby
+                    .bans
+                    .iter()
+                    .map(|ban| BanView {
+                        player_name: ban.player_name.clone(),
+                        reason: ban.reason.clone(),
+                        minutes_left: ban
+                            .expires_at
+                            .map(|expires_at| ((expires_at - get_current_time()) / 60.0).ceil().max(0.0) as usize),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            chat_messages: lobby.visible_chat(player_name),
+            questions_queue: lobby.questions_queue.iter().map(queued_view).collect(),
+            questions_queue_active: lobby.questions_queue_active(),
+            questions_queue_countdown: lobby.questions_queue_countdown.round() as usize,
+            quizmaster_queue: if is_quizmaster {
+                lobby.quizmaster_queue.iter().map(queued_view).collect()
+            } else {
+                Vec::new()
+            },
+            items: lobby
+                .items
+                .iter()
+                .map(|item| ItemView {
+                    id: item.id,
+                    name: is_quizmaster.then(|| item.name.clone()),
+                    answers: item.answers.clone(),
+                    hints: item
+                        .hints
+                        .iter()
+                        .filter(|hint| hint.visible_to(player_name, is_quizmaster))
+                        .map(|hint| hint.text.clone())
+                        .collect(),
+                    clue: item.clue.clone(),
+                })
+                .collect(),
+            questions: lobby
+                .questions
+                .iter()
+                .map(|question| QuestionView {
+                    id: question.id,
+                    player: question.player.clone(),
+                    text: question.visible_to(player_name, is_quizmaster).then(|| question.text.clone()),
+                    masked: question.masked,
+                })
+                .collect(),
+        }
+    }
+
+    // Only the fields that changed since the previous view, None if nothing changed
+    pub fn diff(&self, previous: &Self) -> Option<LobbyViewDiff> {
+        fn changed<T: PartialEq + Clone>(new: &T, old: &T) -> Option<T> {
+            (new != old).then(|| new.clone())
+        }
+        let diff = LobbyViewDiff {
+            state: changed(&self.state, &previous.state),
+            round: changed(&self.round, &previous.round),
+            elapsed_time: changed(&self.elapsed_time, &previous.elapsed_time),
+            time_left: changed(&self.time_left, &previous.time_left),
+            overtime: changed(&self.overtime, &previous.overtime),
+            key_player: changed(&self.key_player, &previous.key_player),
+            invite_code: changed(&self.invite_code, &previous.invite_code),
+            settings: changed(&self.settings, &previous.settings),
+            players: changed(&self.players, &previous.players),
+            bans: changed(&self.bans, &previous.bans),
+            chat_messages: changed(&self.chat_messages, &previous.chat_messages),
+            questions_queue: changed(&self.questions_queue, &previous.questions_queue),
+            questions_queue_active: changed(&self.questions_queue_active, &previous.questions_queue_active),
+            questions_queue_countdown: changed(&self.questions_queue_countdown, &previous.questions_queue_countdown),
+            quizmaster_queue: changed(&self.quizmaster_queue, &previous.quizmaster_queue),
+            items: changed(&self.items, &previous.items),
+            questions: changed(&self.questions, &previous.questions),
+        };
+        (diff != LobbyViewDiff::default()).then_some(diff)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Serialize)]
+pub struct LobbyViewDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<LobbyState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_time: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_left: Option<Option<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overtime: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_player: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<LobbySettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub players: Option<Vec<PlayerReduced>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bans: Option<Vec<BanView>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_messages: Option<Vec<ChatMessage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub questions_queue: Option<Vec<QueuedQuestionView>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub questions_queue_active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub questions_queue_countdown: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quizmaster_queue: Option<Vec<QueuedQuestionView>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<ItemView>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub questions: Option<Vec<QuestionView>>,
+}
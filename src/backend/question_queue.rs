@@ -1,60 +1,176 @@
 use crate::{
-    backend::{alert_popup, openai::query_ai, with_lobby, with_player, LobbyState, QueuedQuestion},
+    backend::{
+        add_chat_message_to_lobby,
+        ai::query_ai,
+        alert_popup,
+        error::{GameError, GameResult},
+        with_lobby, with_player, Lobby, QueueMode, QueuedQuestion,
+    },
     MAX_QUESTION_LENGTH,
 };
-use anyhow::{anyhow, bail, ensure, Result};
 use serde::Deserialize;
 use std::collections::HashMap;
 
-pub async fn submit_question(lobby_id: &str, player_name: &str, question: String, masked: bool) -> Result<()> {
+pub async fn submit_question(lobby_id: &str, player_name: &str, question: String, masked: bool) -> GameResult<()> {
     let mut total_cost = 0;
     let mut has_quizmaster = false;
     with_player(lobby_id, player_name, |lobby, player| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
+        lobby.ensure_playing()?;
+        if lobby.overtime {
+            return Err(GameError::TimeUp);
+        }
         total_cost = if masked {
             lobby.settings.submit_question_cost + lobby.settings.masked_question_cost
         } else {
             lobby.settings.submit_question_cost
         };
-        ensure!(player.coins >= total_cost, "Insufficient coins to submit question");
+        if lobby.coins(player_name) < total_cost {
+            return Err(GameError::InsufficientCoins {
+                required: total_cost,
+                available: lobby.coins(player_name),
+            });
+        }
         has_quizmaster = lobby.settings.player_controlled;
-        ensure!(!player.quizmaster, "Quizmaster cannot engage");
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
         if lobby.questions_queue.iter().any(|q| q.question == question) {
-            bail!("Question already exists in queue");
+            return Err(GameError::QuestionAlreadyQueued);
         }
         Ok(())
     })?;
 
     let validate_response = validate_question(&question, !has_quizmaster).await;
-    ensure!(validate_response.suitable, validate_response.reasoning);
+    if !validate_response.suitable {
+        return Err(GameError::QuestionUnsuitable {
+            reason: validate_response.reasoning,
+        });
+    }
 
-    let question = {
-        let mut question = question.trim().to_owned();
-        if !question.ends_with('?') {
-            question.push('?');
-        }
-        let mut chars = question.chars();
-        if let Some(first_char) = chars.next() {
-            question = first_char.to_uppercase().to_string() + chars.as_str();
-        }
-        question
-    };
+    let question = tidy_question(&question);
 
     with_lobby(lobby_id, |lobby| {
-        let player = lobby
-            .players
-            .get_mut(player_name)
-            .ok_or_else(|| anyhow!("Player '{player_name}' not found"))?;
-
-        ensure!(player.coins >= total_cost, "Insufficient coins to submit question");
-        player.coins -= total_cost;
+        let coins = lobby.coins_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if *coins < total_cost {
+            return Err(GameError::InsufficientCoins {
+                required: total_cost,
+                available: *coins,
+            });
+        }
+        *coins -= total_cost;
+        lobby.queued_questions_counter += 1;
         lobby.questions_queue.push(QueuedQuestion {
+            id: lobby.queued_questions_counter,
             player: player_name.to_owned(),
             question,
             voters: Vec::new(),
+            bids: HashMap::new(),
             masked,
             answers: HashMap::new(),
+            queued_at: lobby.elapsed_time,
+        });
+        Ok(())
+    })
+}
+
+fn tidy_question(question: &str) -> String {
+    let mut question = question.trim().to_owned();
+    if !question.ends_with('?') {
+        question.push('?');
+    }
+    let mut chars = question.chars();
+    if let Some(first_char) = chars.next() {
+        question = first_char.to_uppercase().to_string() + chars.as_str();
+    }
+    question
+}
+
+// Takes a question back out of the queue, the submitter gets part of their cost back and every voter is refunded in full
+pub fn withdraw_question(lobby_id: &str, player_name: &str, question_id: usize) {
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let question_index = lobby
+            .questions_queue
+            .iter()
+            .position(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+        if lobby.questions_queue[question_index].player != player_name {
+            return Err(GameError::NotQuestionOwner);
+        }
+        let question = lobby.questions_queue.remove(question_index);
+
+        let cost = if question.masked {
+            lobby.settings.submit_question_cost + lobby.settings.masked_question_cost
+        } else {
+            lobby.settings.submit_question_cost
+        };
+        let refund = cost * lobby.settings.withdraw_refund_percent / 100;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins += refund;
+        }
+        lobby.refund_backers(&question);
+        if !lobby.questions_queue_active() {
+            lobby.questions_queue_countdown = lobby.settings.submit_question_every_x_seconds as f64;
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// Rewording a question goes through the same validation as submitting it, votes and bids were for the old wording so they're refunded
+pub async fn edit_question(lobby_id: &str, player_name: &str, question_id: usize, question: String) -> GameResult<()> {
+    let mut has_quizmaster = false;
+    with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        if lobby.overtime {
+            return Err(GameError::TimeUp);
+        }
+        let queued_question = lobby
+            .questions_queue
+            .iter()
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+        if queued_question.player != player_name {
+            return Err(GameError::NotQuestionOwner);
+        }
+        if lobby.questions_queue.iter().any(|q| q.id != question_id && q.question == question) {
+            return Err(GameError::QuestionAlreadyQueued);
+        }
+        has_quizmaster = lobby.settings.player_controlled;
+        Ok(())
+    })?;
+
+    let validate_response = validate_question(&question, !has_quizmaster).await;
+    if !validate_response.suitable {
+        return Err(GameError::QuestionUnsuitable {
+            reason: validate_response.reasoning,
         });
+    }
+
+    let question = tidy_question(&question);
+
+    with_lobby(lobby_id, |lobby| {
+        // The question may have been asked or withdrawn while it was being validated
+        let queued_question = lobby
+            .questions_queue
+            .iter_mut()
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+        queued_question.question = question;
+        let voters = std::mem::take(&mut queued_question.voters);
+        let bids = std::mem::take(&mut queued_question.bids);
+        lobby.refund_voters(&voters);
+        lobby.refund_bids(&bids);
+        if !lobby.questions_queue_active() {
+            lobby.questions_queue_countdown = lobby.settings.submit_question_every_x_seconds as f64;
+        }
         Ok(())
     })
 }
@@ -101,41 +217,225 @@ async fn validate_question(question: &str, use_ai: bool) -> ValidateQuestionResp
     }
 }
 
-pub fn vote_question(lobby_id: &str, player_name: &str, question: &String) {
+pub fn vote_question(lobby_id: &str, player_name: &str, question_id: usize) {
     let result = with_lobby(lobby_id, |lobby| {
-        let player = lobby
-            .players
-            .get_mut(player_name)
-            .ok_or_else(|| anyhow!("Player '{player_name}' not found"))?;
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        ensure!(!player.quizmaster, "Quizmaster cannot engage");
-        ensure!(player.coins >= 1, "Insufficient coins");
+        lobby.ensure_playing()?;
+        let available = lobby.coins(player_name);
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if lobby.overtime {
+            return Err(GameError::TimeUp);
+        }
+        if lobby.settings.queue_mode != QueueMode::Voting {
+            return Err(GameError::VotingDisabled);
+        }
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        if available < 1 {
+            return Err(GameError::InsufficientCoins { required: 1, available });
+        }
 
         let queued_question = lobby
             .questions_queue
             .iter_mut()
-            .find(|q| &q.question == question)
-            .ok_or_else(|| anyhow!("Question not found in queue"))?;
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
 
-        player.coins -= 1;
         queued_question.voters.push(player_name.to_owned());
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins -= 1;
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// Takes back one of the player's votes, the coin is only returned if the lobby refunds unvotes
+pub fn unvote_question(lobby_id: &str, player_name: &str, question_id: usize) {
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let queued_question = lobby
+            .questions_queue
+            .iter_mut()
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+        let vote_index = queued_question
+            .voters
+            .iter()
+            .position(|voter| voter == player_name)
+            .ok_or(GameError::NotVoted)?;
+        queued_question.voters.remove(vote_index);
+
+        if lobby.settings.refund_unvotes {
+            lobby.refund_voters(&[player_name.to_owned()]);
+        }
+        if !lobby.questions_queue_active() {
+            lobby.questions_queue_countdown = lobby.settings.submit_question_every_x_seconds as f64;
+        }
         Ok(())
     });
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("Vote rejected {error}"));
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// Adds to the player's sealed bid on a question, bids on questions that lose out to another are refunded when it's asked
+pub fn bid_question(lobby_id: &str, player_name: &str, question_id: usize, amount: usize) {
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let available = lobby.coins(player_name);
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if lobby.overtime {
+            return Err(GameError::TimeUp);
+        }
+        if lobby.settings.queue_mode != QueueMode::Auction {
+            return Err(GameError::BiddingDisabled);
+        }
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        if amount == 0 {
+            return Err(GameError::InvalidBid);
+        }
+        if available < amount {
+            return Err(GameError::InsufficientCoins {
+                required: amount,
+                available,
+            });
+        }
+
+        let queued_question = lobby
+            .questions_queue
+            .iter_mut()
+            .find(|q| q.id == question_id)
+            .ok_or(GameError::QuestionNotFound { question_id })?;
+
+        *queued_question.bids.entry(player_name.to_owned()).or_default() += amount;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins -= amount;
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+// Questions left waiting in the queue too long are dropped and their voters and bidders refunded, the submitter's cost is not returned
+pub fn expire_queued_questions(lobby: &mut Lobby) {
+    if lobby.settings.question_expiry_seconds == 0 {
+        return;
+    }
+    let (expired, kept) = std::mem::take(&mut lobby.questions_queue)
+        .into_iter()
+        .partition::<Vec<_>, _>(|question| lobby.question_expires_in(question) == Some(0.0));
+    lobby.questions_queue = kept;
+    for question in expired {
+        lobby.refund_backers(&question);
+        let message = if question.masked {
+            format!("A masked question from '{}' expired", question.player)
+        } else {
+            format!("Question '{}' expired", question.question)
+        };
+        add_chat_message_to_lobby(lobby, "SYSTEM", &message);
     }
 }
 
 pub fn convert_score(lobby_id: &str, player_name: &str) {
-    let result = with_player(lobby_id, player_name, |lobby, player| {
-        ensure!(lobby.state == LobbyState::Play, "Lobby not started");
-        ensure!(!player.quizmaster, "Quizmaster cannot engage");
-        ensure!(player.score >= 1, "Insufficient score");
+    let result = with_lobby(lobby_id, |lobby| {
+        lobby.ensure_playing()?;
+        let player = lobby.players.get_mut(player_name).ok_or_else(|| GameError::PlayerNotFound {
+            player_name: player_name.to_owned(),
+        })?;
+        if player.quizmaster {
+            return Err(GameError::QuizmasterCannotEngage);
+        }
+        if player.spectator {
+            return Err(GameError::SpectatorCannotEngage);
+        }
+        if player.score < 1 {
+            return Err(GameError::InsufficientScore);
+        }
         player.score -= 1;
-        player.coins += lobby.settings.score_to_coins_ratio;
+        let score_to_coins_ratio = lobby.settings.score_to_coins_ratio;
+        if let Some(coins) = lobby.coins_mut(player_name) {
+            *coins += score_to_coins_ratio;
+        }
         Ok(())
     });
     if let Err(error) = result {
-        alert_popup(lobby_id, player_name, &format!("{error}"));
+        alert_popup(lobby_id, player_name, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::{answer_with, block_on, TestLobby};
+
+    fn submit(test: &TestLobby, player_name: &str, question: &str) -> usize {
+        answer_with(|_| Ok(r#"{"reasoning": "Clear question", "suitable": true}"#.to_owned()));
+        block_on(submit_question(&test.id, player_name, question.to_owned(), false)).unwrap();
+        test.with(|lobby| lobby.queued_questions_counter)
+    }
+
+    #[test]
+    fn withdrawing_refunds_part_of_the_cost_and_every_vote() {
+        let test = TestLobby::new("withdrawrefund", &["alice", "bob"]);
+        test.with(|lobby| lobby.settings.withdraw_refund_percent = 50);
+        test.start();
+        let question_id = submit(&test, "alice", "Is it red?");
+        vote_question("withdrawrefund", "bob", question_id);
+        vote_question("withdrawrefund", "bob", question_id);
+        assert_eq!((test.coins("alice"), test.coins("bob")), (4, 6));
+
+        withdraw_question("withdrawrefund", "alice", question_id);
+        assert_eq!((test.coins("alice"), test.coins("bob")), (6, 8));
+        assert!(test.with(|lobby| lobby.questions_queue.is_empty()));
+    }
+
+    #[test]
+    fn editing_refunds_the_backers() {
+        let test = TestLobby::new("editrefund", &["alice", "bob"]);
+        test.start();
+        let question_id = submit(&test, "alice", "Is it red?");
+        vote_question("editrefund", "bob", question_id);
+        assert_eq!(test.coins("bob"), 7);
+
+        block_on(edit_question("editrefund", "alice", question_id, "is it green".to_owned())).unwrap();
+        let question = test.with(|lobby| lobby.questions_queue[0].clone());
+        assert_eq!((question.question.as_str(), question.voters.len()), ("Is it green?", 0));
+        assert_eq!((test.coins("alice"), test.coins("bob")), (4, 8));
+    }
+
+    #[test]
+    fn expired_questions_refund_the_backers() {
+        let test = TestLobby::new("expiryrefund", &["alice", "bob"]);
+        test.with(|lobby| lobby.settings.question_expiry_seconds = 30);
+        test.start();
+        // No coins are handed out while the clock runs, so only refunds change the balances
+        test.with(|lobby| lobby.coins_countdown = 1000.0);
+        let question_id = submit(&test, "alice", "Is it red?");
+        vote_question("expiryrefund", "bob", question_id);
+
+        test.step(29.0);
+        assert_eq!(test.with(|lobby| lobby.questions_queue.len()), 1);
+        assert_eq!(test.coins("bob"), 7);
+
+        test.step(1.0);
+        assert!(test.with(|lobby| lobby.questions_queue.is_empty()));
+        assert_eq!((test.coins("alice"), test.coins("bob")), (4, 8));
     }
 }
@@ -0,0 +1,85 @@
+use crate::{
+    backend::{get_current_time, FinishedGameRecord, Lobby, FINISHED_GAMES, LOBBYS},
+    SNAPSHOT_FILE,
+};
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// Bump whenever the lobby format changes in a way serde defaults can't fill in, newer snapshots are refused
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    saved_at: f64,
+    lobbies: Vec<Lobby>,
+    #[serde(default)]
+    finished_games: Vec<FinishedGameRecord>,
+}
+
+pub fn save_snapshot() -> Result<()> {
+    let lobbies = LOBBYS.lock().unwrap().values().cloned().collect::<Vec<Lobby>>();
+    let finished_games = FINISHED_GAMES.lock().unwrap().clone();
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        saved_at: get_current_time(),
+        lobbies,
+        finished_games,
+    };
+    let snapshot_str = serde_json::to_string(&snapshot).context("Failed to serialize the snapshot")?;
+
+    // Write to a temporary file first so a crash mid-write never leaves a truncated snapshot
+    let temp_file = format!("{SNAPSHOT_FILE}.tmp");
+    fs::write(&temp_file, snapshot_str).context("Failed to write snapshot file")?;
+    fs::rename(&temp_file, SNAPSHOT_FILE).context("Failed to replace snapshot file")?;
+    Ok(())
+}
+
+pub fn load_snapshot() -> Result<usize> {
+    if !fs::exists(SNAPSHOT_FILE).unwrap_or(false) {
+        return Ok(0);
+    }
+    let snapshot_str = fs::read_to_string(SNAPSHOT_FILE).context("Failed to read snapshot file")?;
+    let snapshot = serde_json::from_str::<Snapshot>(&snapshot_str).context("Failed to parse snapshot file")?;
+    ensure!(
+        snapshot.version <= SNAPSHOT_VERSION,
+        "Snapshot version {} is newer than the supported version {SNAPSHOT_VERSION}",
+        snapshot.version
+    );
+
+    // Shift timestamps by the downtime so players aren't idle kicked and countdowns don't jump
+    let current_time = get_current_time();
+    let downtime = (current_time - snapshot.saved_at).max(0.0);
+    let mut lobbys_lock = LOBBYS.lock().unwrap();
+    let lobbies_count = snapshot.lobbies.len();
+    for mut lobby in snapshot.lobbies {
+        lobby.last_update = current_time;
+        for player in lobby.players.values_mut() {
+            player.last_contact += downtime;
+        }
+        lobbys_lock.insert(lobby.id.clone(), lobby);
+    }
+    drop(lobbys_lock);
+    *FINISHED_GAMES.lock().unwrap() = snapshot.finished_games;
+    Ok(lobbies_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_load_with_defaults() {
+        let lobby_str = r#"{
+            "id": "sparse",
+            "players": {"alice": {"name": "alice", "last_contact": 1.0, "score": 3, "coins": 5}},
+            "settings": {"item_count": 4, "theme": "Fruit"}
+        }"#;
+        let lobby = serde_json::from_str::<Lobby>(lobby_str).unwrap();
+
+        assert_eq!(lobby.players["alice"].coins, 5);
+        assert_eq!(lobby.settings.item_count, 4);
+        assert_eq!(lobby.settings.questions_per_item, 20);
+    }
+}
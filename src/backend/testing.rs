@@ -0,0 +1,132 @@
+use crate::backend::{
+    ai::{set_ai_provider, AiProvider},
+    clock::{set_clock, ManualClock},
+    connect_player, get_current_time, lobby_loop, with_lobby, Lobby, LobbyState, QueuedQuestion, LOBBYS,
+};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+pub const ITEMS: [&str; 6] = ["Apple", "Banana", "Cherry", "Grape", "Lemon", "Mango"];
+
+// The clock, AI and lobby list are global, so tests using them take turns
+static GLOBALS_LOCK: Mutex<()> = Mutex::new(());
+
+// Answers every prompt with a function of it, so async game code runs without the network
+struct FakeAi<F>(F);
+
+impl<F: Fn(&str) -> Result<String> + Send + Sync> AiProvider for FakeAi<F> {
+    fn query<'a>(&'a self, prompt: &'a str, _max_tokens: usize, _temperature: f32, _use_json: bool) -> BoxFuture<'a, Result<String>> {
+        let response = (self.0)(prompt);
+        Box::pin(async move { response })
+    }
+}
+
+pub fn answer_with(answer: impl Fn(&str) -> Result<String> + Send + Sync + 'static) {
+    set_ai_provider(Arc::new(FakeAi(answer)));
+}
+
+// Asking questions and generating clues spawn tasks, so they need a runtime to run in
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+// A lobby on a manual clock that is removed again when the test ends, whether it passed or not
+pub struct TestLobby {
+    pub id: String,
+    pub clock: Arc<ManualClock>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestLobby {
+    // The first player connected is the key player, the AI fails every prompt until told how to answer
+    pub fn new(lobby_id: &str, player_names: &[&str]) -> Self {
+        let guard = GLOBALS_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let clock = Arc::new(ManualClock::new(1000.0));
+        set_clock(clock.clone());
+        let test_lobby = Self {
+            id: lobby_id.to_owned(),
+            clock,
+            _guard: guard,
+        };
+        answer_with(|_| Err(anyhow!("No AI in tests")));
+        for player_name in player_names {
+            connect_player(lobby_id, player_name, "", "").unwrap();
+        }
+        test_lobby
+    }
+
+    pub fn with<T>(&self, f: impl FnOnce(&mut Lobby) -> T) -> T {
+        with_lobby(&self.id, |lobby| Ok(f(lobby))).unwrap()
+    }
+
+    pub fn coins(&self, player_name: &str) -> usize {
+        self.with(|lobby| lobby.coins(player_name))
+    }
+
+    // Starts the game the way the lobby loop does once the items are generated
+    pub fn start(&self) {
+        self.with(|lobby| {
+            lobby.state = LobbyState::Starting;
+            lobby.round = 1;
+            lobby.items_queue = ITEMS.map(str::to_owned).to_vec();
+        });
+        self.step(1.0);
+    }
+
+    // Skips straight into play with no items on the board
+    pub fn play(&self) {
+        self.with(|lobby| {
+            lobby.state = LobbyState::Play;
+            lobby.last_update = get_current_time();
+        });
+    }
+
+    // Queues a question without paying for or validating it
+    pub fn queue_question(&self, player_name: &str, question: &str, voters: &[&str]) -> usize {
+        self.with(|lobby| {
+            lobby.queued_questions_counter += 1;
+            lobby.questions_queue.push(QueuedQuestion {
+                id: lobby.queued_questions_counter,
+                player: player_name.to_owned(),
+                question: question.to_owned(),
+                masked: false,
+                voters: voters.iter().map(|voter| (*voter).to_owned()).collect(),
+                bids: HashMap::new(),
+                answers: HashMap::new(),
+                queued_at: lobby.elapsed_time,
+            });
+            lobby.queued_questions_counter
+        })
+    }
+
+    // Keeps a player from idling out while the clock is stepped forward
+    pub fn touch(&self, player_name: &str) {
+        self.with(|lobby| lobby.players.get_mut(player_name).unwrap().last_contact = get_current_time());
+    }
+
+    // Moves the clock on with every player still connected, then runs the lobby loop
+    pub fn step(&self, seconds: f64) {
+        self.clock.advance(seconds);
+        self.with(|lobby| {
+            for player in lobby.players.values_mut() {
+                player.last_contact = get_current_time();
+            }
+        });
+        lobby_loop();
+    }
+}
+
+impl Drop for TestLobby {
+    fn drop(&mut self) {
+        LOBBYS.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.id);
+    }
+}
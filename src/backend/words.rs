@@ -1,11 +1,9 @@
-use crate::backend::{openai::query_ai, with_lobby, Difficulty};
-use anyhow::ensure;
-use once_cell::sync::Lazy;
+use crate::backend::{ai::query_ai, with_lobby, Difficulty};
 use rand::{
     distributions::{Distribution, WeightedIndex},
     thread_rng,
 };
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, LazyLock, Mutex};
 
 fn generate_weighted_string(length: usize) -> Vec<String> {
     let letters = [
@@ -76,7 +74,7 @@ async fn get_ai_words(theme: String, difficulty: Difficulty, items: usize, item_
     items_return
 }
 
-static LOBBYS_PROCESSING: Lazy<Arc<Mutex<Vec<String>>>> = Lazy::new(|| Arc::new(Mutex::new(Vec::new())));
+static LOBBYS_PROCESSING: LazyLock<Arc<Mutex<Vec<String>>>> = LazyLock::new(|| Arc::new(Mutex::new(Vec::new())));
 
 async fn topup_lobby(lobby_id: &str) {
     let (mut items_queue, mut item_count, mut theme, mut difficulty) = (Vec::new(), 0, String::new(), Difficulty::Easy);
@@ -95,7 +93,9 @@ async fn topup_lobby(lobby_id: &str) {
     let words = get_ai_words(theme, difficulty, items_needed, items_queue).await;
     let _result = with_lobby(lobby_id, |lobby| {
         let (items_queue, item_count) = (&mut lobby.items_queue, lobby.settings.item_count);
-        ensure!(items_queue.len() < item_count, "Item queue is full");
+        if items_queue.len() >= item_count {
+            return Ok(());
+        }
         items_queue.extend(words.into_iter().take(item_count - items_queue.len()));
         Ok(())
     });
@@ -14,6 +14,10 @@ document.addEventListener("click", function (event) {
     if (event.target.nodeName === "BUTTON") {
         playSound("button_pressed", 1.0);
         event.target.blur();
+        const copyPath = event.target.getAttribute("data-copy");
+        if (copyPath) {
+            navigator.clipboard.writeText(new URL(copyPath, window.location.href).href);
+        }
     }
 });
 
@@ -38,6 +42,9 @@ document.addEventListener("input", function (event) {
                 event.target.value = event.target.value.slice(0, -1);
             }
         }
+        if (event.target.id === "player-name") {
+            localStorage.setItem("playerName", event.target.value);
+        }
     }
 });
 
@@ -55,3 +62,45 @@ setInterval(() => {
         });
     }
 }, 200);
+
+// Remember the session the server renders, and offer it back once to rejoin after a refresh
+let sessionRestoreAttempted = false;
+setInterval(() => {
+    const sessionElement = document.getElementById("session");
+    if (sessionElement) {
+        if (sessionElement.textContent) {
+            localStorage.setItem("session", sessionElement.textContent);
+        } else {
+            localStorage.removeItem("session");
+        }
+    }
+    const restoreForm = document.getElementById("session-restore");
+    const storedSession = localStorage.getItem("session");
+    if (restoreForm && storedSession && !sessionRestoreAttempted) {
+        sessionRestoreAttempted = true;
+        restoreForm.querySelector("input").value = storedSession;
+        restoreForm.requestSubmit();
+    }
+}, 200);
+
+// Invite links such as /?lobby=abc&code=xyz fill in the join form, and join straight away if a name is remembered
+const inviteParams = new URLSearchParams(window.location.search);
+let inviteAttempted = !inviteParams.get("lobby");
+function fillInput(id, value) {
+    const input = document.getElementById(id);
+    input.value = value;
+    input.dispatchEvent(new Event("input", { bubbles: true }));
+}
+setInterval(() => {
+    const joinForm = document.getElementById("join-lobby");
+    if (joinForm && !inviteAttempted && !localStorage.getItem("session")) {
+        inviteAttempted = true;
+        fillInput("lobby-id", inviteParams.get("lobby"));
+        fillInput("lobby-password", inviteParams.get("code") || "");
+        const playerName = localStorage.getItem("playerName");
+        if (playerName) {
+            fillInput("player-name", playerName);
+            joinForm.requestSubmit();
+        }
+    }
+}, 200);
@@ -1,7 +1,12 @@
 use crate::{
-    backend::{connect_player, get_current_time, get_lobby_info, get_state, Lobby, LobbyState, Player, PlayerMessage},
     frontend::{gamesettings::GameSettings, gameview::GameView},
-    CLIENT_UPDATE_INTERVAL, LOBBY_ID_PATTERN, MAX_LOBBY_ID_LENGTH, MAX_PLAYER_NAME_LENGTH, PLAYER_NAME_PATTERN,
+    CLIENT_UPDATE_INTERVAL,
+};
+use deducers::{
+    backend::{
+        connect_player, get_current_time, get_lobby_info, get_state, reconnect_player, spectate_lobby, Lobby, LobbyState, PlayerMessage,
+    },
+    LOBBY_ID_PATTERN, LOBBY_PASSWORD_PATTERN, MAX_LOBBY_ID_LENGTH, MAX_LOBBY_PASSWORD_LENGTH, MAX_PLAYER_NAME_LENGTH, PLAYER_NAME_PATTERN,
 };
 use dioxus::prelude::*;
 use std::time::Duration;
@@ -130,7 +135,10 @@ pub fn tutorial(tutorial_open: &UseState<bool>) -> LazyNodes<'_, '_> {
 pub fn app(cx: Scope) -> Element {
     let player_name = use_state(cx, String::new);
     let lobby_id = use_state(cx, String::new);
+    let lobby_password = use_state(cx, String::new);
     let is_connected = use_state(cx, || false);
+    let session_token = use_state(cx, String::new);
+    let session_restore_failed = use_state(cx, || false);
 
     let lobby_state = use_state(cx, || None::<Lobby>);
     let lobby_info = use_state(cx, Vec::new);
@@ -191,6 +199,14 @@ pub fn app(cx: Scope) -> Element {
                             }
                             "guess_incorrect;0.5"
                         }
+                        PlayerMessage::RoundOver(round_message) => {
+                            item_reveal_message.set(ItemRevealMessage::new(8.0, round_message.clone(), RevealType::Victory));
+                            "guess_correct;0.5"
+                        }
+                        PlayerMessage::TimeUp(message) => {
+                            item_reveal_message.set(ItemRevealMessage::new(5.0, message.clone(), RevealType::Incorrect));
+                            "guess_incorrect;0.5"
+                        }
                         PlayerMessage::Winner(win_message) => {
                             item_reveal_message.set(ItemRevealMessage::new(30.0, win_message.clone(), RevealType::Victory));
                             "guess_correct;0.5"
@@ -199,14 +215,18 @@ pub fn app(cx: Scope) -> Element {
                             alert_popup.set(AlertPopup::message(format!("Question '{message}' rejected by quizmaster")));
                             "guess_incorrect;0.5"
                         }
-                        PlayerMessage::AlertPopup(message) => {
-                            alert_popup.set(AlertPopup::message(message.clone()));
+                        PlayerMessage::AlertPopup(error) => {
+                            alert_popup.set(AlertPopup::message(error.to_string()));
                             ""
                         }
-                        PlayerMessage::PlayerKicked => {
+                        PlayerMessage::PlayerKicked(reason) => {
                             error_message.set(ErrorDialog {
                                 show: true,
-                                str: "You were kicked from the lobby".to_string(),
+                                str: if reason.is_empty() {
+                                    "You were kicked from the lobby".to_string()
+                                } else {
+                                    format!("You were kicked from the lobby: {reason}")
+                                },
                             });
                             ""
                         }
@@ -276,35 +296,62 @@ pub fn app(cx: Scope) -> Element {
                         player_name: player_name.get().clone(),
                         lobby_id: lobby_id.get().clone(),
                         key_player: lobby.key_player.clone(),
-                        started: matches!(lobby.state, LobbyState::Play | LobbyState::Starting),
+                        started: matches!(lobby.state, LobbyState::Play | LobbyState::Paused | LobbyState::Starting),
+                        playing: lobby.state == LobbyState::Play,
+                        paused: lobby.state == LobbyState::Paused,
+                        ended: lobby.state == LobbyState::Ended,
+                        round: lobby.round,
                         elapsed_time: lobby.elapsed_time.round() as usize,
+                        time_left: lobby.time_left().map(|time_left| time_left.ceil() as usize),
+                        overtime: lobby.overtime,
                         settings: lobby.settings.clone(),
                         questions_queue: lobby.questions_queue.clone(),
                         questions_queue_active: lobby.questions_queue_active(),
                         questions_queue_countdown: lobby.questions_queue_countdown.round() as usize,
                         quizmaster_queue: lobby.quizmaster_queue.clone(),
-                        players: lobby.players.values().map(Player::reduce).collect(),
+                        players: lobby.players.values().map(|player| player.reduce(lobby, player_name.get())).collect(),
+                        bans: lobby.bans.clone(),
                         items: lobby.items.clone(),
                         questions: lobby.questions.clone(),
-                        chat_messages: lobby.chat_messages.clone(),
+                        chat_messages: lobby.visible_chat(player_name),
                         alert_popup_message: alert_popup.get().message.clone()
                     }
                     render_error_dialog,
                     div { class: "dialog {reveal_message.show}", background_color: reveal_message.revealtype.to_color(), "{reveal_message.str}" }
-                    if player_name == &lobby.key_player && !matches!(lobby.state, LobbyState::Play | LobbyState::Starting) {
+                    if player_name == &lobby.key_player && lobby.state == LobbyState::Open {
                         rsx! { GameSettings {
                             player_name: player_name.get().clone(),
                             lobby_id: lobby_id.get().clone(),
+                            invite_code: lobby.invite_code.clone(),
+                            password: lobby.password.clone(),
                             settings: lobby.settings.clone(),
                             items_queue: lobby.items_queue.clone(),
                         }}
                     }
                     div { id: "sounds", visibility: "collapse", position: "absolute", "{sounds_str}" }
+                    div { id: "session", visibility: "collapse", position: "absolute", "{lobby_id};{player_name};{session_token}" }
                 })
             },
         )
     } else {
         let is_lobby_valid = lobby_info.get().iter().any(|lobby| lobby.id == *lobby_id.get());
+        let join_lobby = move |id: String, spectator: bool| {
+            lobby_state.set(None);
+            let join = if spectator { spectate_lobby } else { connect_player };
+            match join(&id, player_name, lobby_password, session_token) {
+                Ok(token) => {
+                    lobby_id.set(id);
+                    session_token.set(token);
+                    is_connected.set(true);
+                }
+                Err(error) => {
+                    error_message.set(ErrorDialog {
+                        show: true,
+                        str: format!("Failed to connect to lobby: {error}"),
+                    });
+                }
+            }
+        };
         cx.render(rsx! {
             div {
                 display: "flex",
@@ -314,6 +361,7 @@ pub fn app(cx: Scope) -> Element {
                 height: "calc(100vh - 40px)",
                 img { src: "/assets/deducers_banner2.png", width: "400px", padding: "20px" }
                 input {
+                    id: "player-name",
                     r#type: "text",
                     placeholder: "Player Name",
                     pattern: PLAYER_NAME_PATTERN,
@@ -323,20 +371,13 @@ pub fn app(cx: Scope) -> Element {
                     }
                 }
                 form {
+                    id: "join-lobby",
                     class: "background-box",
                     onsubmit: move |_| {
-                        lobby_state.set(None);
-                        if let Err(error) = connect_player(lobby_id, player_name) {
-                            error_message
-                                .set(ErrorDialog {
-                                    show: true,
-                                    str: format!("Failed to connect to lobby: {error}"),
-                                });
-                        } else {
-                            is_connected.set(true);
-                        }
+                        join_lobby(lobby_id.get().clone(), false);
                     },
                     input {
+                        id: "lobby-id",
                         r#type: "text",
                         placeholder: "Lobby Id",
                         pattern: LOBBY_ID_PATTERN,
@@ -345,29 +386,42 @@ pub fn app(cx: Scope) -> Element {
                             lobby_id.set(e.value.clone());
                         }
                     }
+                    input {
+                        id: "lobby-password",
+                        r#type: "password",
+                        placeholder: "Password (if any)",
+                        pattern: LOBBY_PASSWORD_PATTERN,
+                        maxlength: MAX_LOBBY_PASSWORD_LENGTH as i64,
+                        oninput: move |e| {
+                            lobby_password.set(e.value.clone());
+                        }
+                    }
                     button { r#type: "submit",
                         if is_lobby_valid { "Join" } else { "Create Lobby" }
                     }
                 }
                 div { class: "background-box",
-                    for lobby in lobby_info.get().iter().filter(|lobby| !lobby.started) {
+                    for lobby in lobby_info.get().iter() {
                         div { display: "flex", flex_direction: "row", align_items: "center", gap: "5px",
-                            div { "{lobby.id}: {lobby.players_count} Players" }
+                            div {
+                                "{lobby.id}: {lobby.players_count} Players"
+                                if lobby.spectators_count > 0 { format!(", {} Watching", lobby.spectators_count) } else { String::new() }
+                                if lobby.password_protected { " 🔒" } else { "" }
+                                if lobby.started { " (In Progress)" } else if lobby.state == LobbyState::Ended { " (Finished)" } else { "" }
+                            }
+                            if !lobby.started {
+                                rsx! { button {
+                                    onclick: move |_| {
+                                        join_lobby(lobby.id.clone(), false);
+                                    },
+                                    "Join"
+                                }}
+                            }
                             button {
                                 onclick: move |_| {
-                                    lobby_id.set(lobby.id.clone());
-                                    lobby_state.set(None);
-                                    if let Err(error) = connect_player(&lobby.id, player_name) {
-                                        error_message
-                                            .set(ErrorDialog {
-                                                show: true,
-                                                str: format!("Failed to connect to lobby: {error}"),
-                                            });
-                                    } else {
-                                        is_connected.set(true);
-                                    }
+                                    join_lobby(lobby.id.clone(), true);
                                 },
-                                "Join"
+                                "Spectate"
                             }
                         }
                     }
@@ -378,6 +432,34 @@ pub fn app(cx: Scope) -> Element {
                     },
                     "Learn How To Play"
                 }
+                // Filled and submitted by the client with the stored session to rejoin after a refresh
+                form {
+                    id: "session-restore",
+                    display: "none",
+                    onsubmit: move |form_data| {
+                        let session = form_data.values.get("session").and_then(|m| m.first()).cloned().unwrap_or_default();
+                        let parts = session.splitn(3, ';').collect::<Vec<&str>>();
+                        let restored = match parts[..] {
+                            [restore_lobby_id, restore_player_name, token] => {
+                                reconnect_player(restore_lobby_id, restore_player_name, token)
+                                    .map(|()| {
+                                        lobby_id.set(restore_lobby_id.to_owned());
+                                        player_name.set(restore_player_name.to_owned());
+                                        session_token.set(token.to_owned());
+                                        lobby_state.set(None);
+                                        is_connected.set(true);
+                                    })
+                                    .is_ok()
+                            }
+                            _ => false,
+                        };
+                        session_restore_failed.set(!restored);
+                    },
+                    input { name: "session" }
+                }
+                if *session_restore_failed.get() {
+                    rsx! { div { id: "session", visibility: "collapse", position: "absolute" } }
+                }
             }
             render_error_dialog,
             tutorial(tutorial_open)
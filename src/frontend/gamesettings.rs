@@ -1,19 +1,38 @@
-use crate::{
-    backend::{alter_lobby_settings, start_lobby, AlterLobbySetting, Difficulty, LobbySettings},
-    ITEM_NAME_PATTERN, MAX_ITEM_NAME_LENGTH, MAX_LOBBY_ITEMS, QUESTION_PATTERN,
+use deducers::{
+    backend::{
+        alter_lobby_settings, start_lobby, AlterLobbySetting, Difficulty, LobbySettings, QueueMode, RewardKind, ScoringCurve, Visibility,
+        ADVANCED_SETTINGS,
+    },
+    ITEM_NAME_PATTERN, LOBBY_PASSWORD_PATTERN, MAX_ITEM_NAME_LENGTH, MAX_LOBBY_ITEMS, MAX_LOBBY_PASSWORD_LENGTH, MAX_TEAMS,
+    MAX_TEAM_NAME_LENGTH, QUESTION_PATTERN,
 };
 use dioxus::prelude::*;
 use strum::IntoEnumIterator;
 
 #[component]
-pub fn GameSettings(cx: Scope, player_name: String, lobby_id: String, settings: LobbySettings, items_queue: Vec<String>) -> Element {
+pub fn GameSettings(
+    cx: Scope,
+    player_name: String,
+    lobby_id: String,
+    invite_code: String,
+    password: String,
+    settings: LobbySettings,
+    items_queue: Vec<String>,
+) -> Element {
     let advanced_settings_toggle = use_state(cx, || false);
     let player_controlled = settings.player_controlled;
     let game_time = calculate_game_time(
         settings.item_count,
         settings.submit_question_every_x_seconds,
         settings.add_item_every_x_questions,
+        settings.questions_per_item,
+        settings.rounds,
     );
+    let backers = if settings.queue_mode == QueueMode::Auction {
+        "Bidders"
+    } else {
+        "Voters"
+    };
 
     let alter_setting = {
         move |setting: AlterLobbySetting| {
@@ -37,6 +56,18 @@ pub fn GameSettings(cx: Scope, player_name: String, lobby_id: String, settings:
                     lobby_id: lobby_id.clone(),
                     settings: settings.clone()
                 }
+                AccessSettings {
+                    player_name: player_name.clone(),
+                    lobby_id: lobby_id.clone(),
+                    invite_code: invite_code.clone(),
+                    password: password.clone(),
+                    settings: settings.clone()
+                }
+                TeamSettings {
+                    player_name: player_name.clone(),
+                    lobby_id: lobby_id.clone(),
+                    settings: settings.clone()
+                }
                 div { display: "flex", flex_direction: "row", gap: "5px",
                     div { class: "dark-box",
                         label {
@@ -82,18 +113,70 @@ pub fn GameSettings(cx: Scope, player_name: String, lobby_id: String, settings:
                 }
             }
         }
+        div { display: "flex", gap: "5px",
+            "Informative Reward:"
+            for variant in RewardKind::iter() {
+                button {
+                    class: if settings.informative_reward_kind == variant { "highlighted" } else { "" },
+                    onclick: move |_| {
+                        alter_lobby_settings(lobby_id, player_name, AlterLobbySetting::InformativeRewardKind(variant));
+                    },
+                    "{variant}"
+                }
+            }
+        }
+        label {
+            "Reward {backers}: "
+            input {
+                r#type: "checkbox",
+                checked: "{settings.reward_voters}",
+                oninput: move |e| {
+                    alter_lobby_settings(lobby_id, player_name, AlterLobbySetting::RewardVoters(e.value.parse::<bool>().unwrap_or(false)));
+                }
+            }
+        }
+        label {
+            "Public Hints: "
+            input {
+                r#type: "checkbox",
+                checked: "{settings.public_hints}",
+                oninput: move |e| {
+                    alter_lobby_settings(lobby_id, player_name, AlterLobbySetting::PublicHints(e.value.parse::<bool>().unwrap_or(false)));
+                }
+            }
+        }
+        label {
+            "Refund Unvotes: "
+            input {
+                r#type: "checkbox",
+                checked: "{settings.refund_unvotes}",
+                oninput: move |e| {
+                    alter_lobby_settings(
+                        lobby_id,
+                        player_name,
+                        AlterLobbySetting::RefundUnvotes(e.value.parse::<bool>().unwrap_or(false)),
+                    );
+                }
+            }
+        }
     })
 }
 
-fn calculate_game_time(items_count: usize, question_every_x_seconds: usize, item_every_x_questions: usize) -> String {
+fn calculate_game_time(
+    items_count: usize,
+    question_every_x_seconds: usize,
+    item_every_x_questions: usize,
+    questions_per_item: usize,
+    rounds: usize,
+) -> String {
     // Calculate the total number of questions required
     // Initial two items are added to begin with, additional items need 'item_every_x_questions' each
     let total_questions = if items_count > 2 {
-        20 + (items_count - 2) * item_every_x_questions
+        questions_per_item + (items_count - 2) * item_every_x_questions
     } else {
-        20
+        questions_per_item
     };
-    let game_time_seconds = total_questions * question_every_x_seconds;
+    let game_time_seconds = total_questions * question_every_x_seconds * rounds;
 
     if game_time_seconds < 60 {
         format!("{game_time_seconds} seconds")
@@ -142,6 +225,34 @@ pub fn StandardSettings(cx: Scope, player_name: String, lobby_id: String, settin
                 }
             }
         }
+        div { display: "flex", gap: "5px",
+            "Question Queue:"
+            for variant in QueueMode::iter() {
+                button {
+                    class: if settings.queue_mode == variant { "highlighted" } else { "" },
+                    onclick: {
+                        move |_| {
+                            alter_setting(AlterLobbySetting::QueueMode(variant));
+                        }
+                    },
+                    "{variant}"
+                }
+            }
+        }
+        div { display: "flex", gap: "5px",
+            "Scoring:"
+            for variant in ScoringCurve::iter() {
+                button {
+                    class: if settings.scoring_curve == variant { "highlighted" } else { "" },
+                    onclick: {
+                        move |_| {
+                            alter_setting(AlterLobbySetting::ScoringCurve(variant));
+                        }
+                    },
+                    "{variant}"
+                }
+            }
+        }
         if !settings.player_controlled {
             rsx! { label {
                 "Item Count: "
@@ -162,6 +273,116 @@ pub fn StandardSettings(cx: Scope, player_name: String, lobby_id: String, settin
     })
 }
 
+#[component]
+pub fn AccessSettings(
+    cx: Scope,
+    player_name: String,
+    lobby_id: String,
+    invite_code: String,
+    password: String,
+    settings: LobbySettings,
+) -> Element {
+    let alter_setting = {
+        move |setting: AlterLobbySetting| {
+            alter_lobby_settings(lobby_id, player_name, setting);
+        }
+    };
+    cx.render(rsx! {
+        div { display: "flex", gap: "5px",
+            "Visibility:"
+            for variant in Visibility::iter() {
+                button {
+                    class: if settings.visibility == variant { "highlighted" } else { "" },
+                    onclick: {
+                        move |_| {
+                            alter_setting(AlterLobbySetting::Visibility(variant));
+                        }
+                    },
+                    "{variant}"
+                }
+            }
+        }
+        div { display: "flex", gap: "5px", align_items: "center",
+            if settings.visibility == Visibility::Password {
+                rsx! { form {
+                    onsubmit: move |form_data| {
+                        if let Some(password) = form_data.values.get("password").and_then(|m| m.first()) {
+                            alter_setting(AlterLobbySetting::Password(password.clone()));
+                        }
+                    },
+                    label {
+                        "Password: "
+                        input {
+                            r#type: "text",
+                            name: "password",
+                            value: "{password}",
+                            placeholder: "Invite link only",
+                            maxlength: MAX_LOBBY_PASSWORD_LENGTH as i64,
+                            pattern: LOBBY_PASSWORD_PATTERN
+                        }
+                    }
+                    button { r#type: "submit", "Set Password" }
+                }}
+            }
+            // The client copies the full url, the server only knows the path
+            button { "data-copy": "/?lobby={lobby_id}&code={invite_code}", "Copy Invite Link" }
+        }
+    })
+}
+
+#[component]
+pub fn TeamSettings(cx: Scope, player_name: String, lobby_id: String, settings: LobbySettings) -> Element {
+    let alter_setting = {
+        move |setting: AlterLobbySetting| {
+            alter_lobby_settings(lobby_id, player_name, setting);
+        }
+    };
+    let teams_str = settings.teams.join(", ");
+    cx.render(rsx! {
+        div { display: "flex", gap: "5px", align_items: "center",
+            label {
+                "Teams: "
+                input {
+                    r#type: "checkbox",
+                    checked: "{settings.team_mode}",
+                    oninput: move |e| {
+                        alter_setting(AlterLobbySetting::TeamMode(e.value.parse::<bool>().unwrap_or(false)));
+                    }
+                }
+            }
+            if settings.team_mode {
+                rsx! {
+                    label {
+                        "Pooled coins: "
+                        input {
+                            r#type: "checkbox",
+                            checked: "{settings.pooled_coins}",
+                            oninput: move |e| {
+                                alter_setting(AlterLobbySetting::PooledCoins(e.value.parse::<bool>().unwrap_or(false)));
+                            }
+                        }
+                    }
+                    form {
+                        onsubmit: move |form_data| {
+                            if let Some(teams) = form_data.values.get("teams").and_then(|m| m.first()) {
+                                let teams = teams.split(',').map(|team| team.trim().to_owned()).collect();
+                                alter_setting(AlterLobbySetting::Teams(teams));
+                            }
+                        },
+                        input {
+                            r#type: "text",
+                            name: "teams",
+                            placeholder: "{teams_str}",
+                            maxlength: (MAX_TEAMS * (MAX_TEAM_NAME_LENGTH + 2)) as i64
+                        }
+                        button { r#type: "submit", "Set Teams" }
+                    }
+                }
+            }
+        }
+    })
+}
+
 #[component]
 pub fn ItemSettings(cx: Scope, player_name: String, lobby_id: String, items_queue: Vec<String>, settings: LobbySettings) -> Element {
     let alter_setting = {
@@ -267,17 +488,11 @@ impl SettingDetail {
 
 #[component]
 pub fn AdvancedSettings(cx: Scope, player_name: String, lobby_id: String, settings: LobbySettings) -> Element {
-    let setting_details = vec![
-        SettingDetail::new("starting_coins", 0, 1000, settings.starting_coins),
-        SettingDetail::new("coin_every_x_seconds", 0, 30, settings.coin_every_x_seconds),
-        SettingDetail::new("submit_question_every_x_seconds", 1, 50, settings.submit_question_every_x_seconds),
-        SettingDetail::new("add_item_every_x_questions", 1, 20, settings.add_item_every_x_questions),
-        SettingDetail::new("submit_question_cost", 0, 100, settings.submit_question_cost),
-        SettingDetail::new("masked_question_cost", 0, 100, settings.masked_question_cost),
-        SettingDetail::new("guess_item_cost", 0, 100, settings.guess_item_cost),
-        SettingDetail::new("question_min_votes", 0, 20, settings.question_min_votes),
-        SettingDetail::new("score_to_coins_ratio", 0, 100, settings.score_to_coins_ratio),
-    ];
+    let mut settings = settings.clone();
+    let setting_details = ADVANCED_SETTINGS
+        .iter()
+        .filter_map(|&(key, min, max)| Some(SettingDetail::new(key, min, max, *settings.advanced_mut(key)?)))
+        .collect::<Vec<_>>();
 
     cx.render(rsx! {
         for setting in setting_details.into_iter() {
@@ -1,8 +1,11 @@
-use crate::{
-    backend::{add_chat_message, disconnect_player, ChatMessage, Item, LobbySettings, PlayerReduced, Question, QueuedQuestion},
-    frontend::{
-        items_display::ItemDisplay, leaderboard_display::Leaderboard, management_display::Management,
-        question_queue_display::QuestionQueueDisplay, quizmaster::QuizmasterDisplay,
+use crate::frontend::{
+    items_display::ItemDisplay, leaderboard_display::Leaderboard, management_display::Management,
+    question_queue_display::QuestionQueueDisplay, quizmaster::QuizmasterDisplay,
+};
+use deducers::{
+    backend::{
+        add_chat_message, disconnect_player, join_team, pause_lobby, rematch_lobby, resume_lobby, Ban, ChatMessage, Item, LobbySettings,
+        PlayerReduced, Question, QueuedQuestion,
     },
     MAX_CHAT_LENGTH,
 };
@@ -15,29 +18,55 @@ pub fn GameView(
     lobby_id: String,
     key_player: String,
     started: bool,
+    playing: bool,
+    paused: bool,
+    ended: bool,
+    round: usize,
     elapsed_time: usize,
+    #[props(!optional)] time_left: Option<usize>,
+    overtime: bool,
     settings: LobbySettings,
     questions_queue: Vec<QueuedQuestion>,
     questions_queue_active: bool,
     questions_queue_countdown: usize,
     quizmaster_queue: Vec<QueuedQuestion>,
     players: Vec<PlayerReduced>,
+    bans: Vec<Ban>,
     items: Vec<Item>,
     questions: Vec<Question>,
     chat_messages: Vec<ChatMessage>,
     alert_popup_message: String,
 ) -> Element {
     let is_keyplayer = player_name == key_player;
-    let is_quizmaster = is_keyplayer && settings.player_controlled;
+    let is_quizmaster = players.iter().any(|p| &p.name == player_name && p.quizmaster);
+    let is_spectator = players.iter().any(|p| &p.name == player_name && p.spectator);
+    let my_team = players.iter().find(|p| &p.name == player_name).and_then(|p| p.team);
+    let team_chat = use_state(cx, || false);
 
     cx.render(rsx! {
+        div { class: "dialog {paused}", display: "flex", flex_direction: "column", gap: "10px", align_items: "center",
+            div { font_weight: "bold", "Game Paused" }
+            if is_keyplayer {
+                rsx! { button { onclick: move |_| {
+                        resume_lobby(lobby_id, player_name);
+                    },
+                    "Resume"
+                } }
+            } else {
+                rsx! { div { "Waiting for the host to resume the game" } }
+            }
+        }
         div { display: "flex", height: "calc(100vh - 40px)", gap: "20px",
             div { class: "background-box", flex: "1.5", overflow_y: "auto",
                 ItemDisplay {
                     player_name: player_name.to_owned(),
+                    lobby_id: lobby_id.to_owned(),
                     is_quizmaster: is_quizmaster,
+                    can_unmask: !is_quizmaster && !is_spectator,
+                    unmask_cost: settings.unmask_cost,
                     items: items.clone(),
-                    questions: questions.clone()
+                    questions: questions.clone(),
+                    questions_per_item: settings.questions_per_item
                 }
             }
             div { flex: "1", display: "flex", flex_direction: "column", gap: "20px", overflow_y: "auto",
@@ -55,11 +84,28 @@ pub fn GameView(
                         if !settings.theme.trim().is_empty() {
                             rsx! { div { font_weight: "bold", "Theme ", span { font_weight: "normal", "{settings.theme}" } } }
                         }
+                        if settings.rounds > 1 && *round > 0 {
+                            rsx! { div { font_weight: "bold", "Round ", span { font_weight: "normal", "{round}/{settings.rounds}" } } }
+                        }
                         div { font_weight: "bold",
                             "Time "
                             span { font_weight: "normal", "{elapsed_time}s" }
                         }
+                        if let Some(time_left) = time_left {
+                            if *overtime {
+                                rsx! { div { font_weight: "bold", color: "rgb(200, 50, 50)", "Final Guesses ", span { font_weight: "normal", "{time_left}s" } } }
+                            } else {
+                                rsx! { div { font_weight: "bold", "Time Left ", span { font_weight: "normal", "{time_left / 60}:{time_left % 60:02}" } } }
+                            }
+                        }
                         div { display: "flex", gap: "5px",
+                            if is_keyplayer && *playing {
+                                rsx! { button { onclick: move |_| {
+                                        pause_lobby(lobby_id, player_name);
+                                    },
+                                    "Pause"
+                                } }
+                            }
                             button { onclick: move |_| {
                                     disconnect_player(lobby_id, player_name);
                                 },
@@ -72,14 +118,18 @@ pub fn GameView(
                             player_name: player_name.to_owned(),
                             lobby_id: lobby_id.to_owned(),
                             players: players.clone(),
+                            bans: bans.clone(),
+                            teams: if settings.team_mode { settings.teams.clone() } else { Vec::new() },
                             is_keyplayer: is_keyplayer
                         }
                     }
                 }
                 div { display: "flex", flex_direction: "column", gap: "5px",
                     div { class: "background-box",
-                        if *started {
-                            if player_name == key_player && settings.player_controlled {
+                        if is_spectator {
+                            rsx! { div { align_self: "center", font_size: "larger", "Spectating" } }
+                        } else if *started {
+                            if is_quizmaster {
                                 rsx! {
                                     QuizmasterDisplay {
                                         player_name: player_name.clone(),
@@ -94,13 +144,45 @@ pub fn GameView(
                                         player_name: player_name.to_owned(),
                                         lobby_id: lobby_id.to_owned(),
                                         settings: settings.clone(),
-                                        players_coins: players.iter().find(|p| &p.name == player_name).map_or(0, |p| p.coins),
+                                        players_coins: players.iter().find(|p| &p.name == player_name).and_then(|p| p.coins).unwrap_or_default(),
                                         items: items.clone(),
                                     }
                                 }
                             }
+                        } else if *ended {
+                            rsx! {
+                                div { align_self: "center", font_size: "larger", "Game Over" }
+                                if settings.rounds > 1 {
+                                    rsx! { SeriesScoreboard { players: players.clone(), rounds: settings.rounds } }
+                                }
+                                if is_keyplayer {
+                                    rsx! { button {
+                                        onclick: move |_| {
+                                            rematch_lobby(lobby_id, player_name);
+                                        },
+                                        "Play Again"
+                                    }}
+                                } else {
+                                    rsx! { div { align_self: "center", "Waiting for the host to start a rematch" } }
+                                }
+                            }
                         } else {
-                            rsx! { div { align_self: "center", font_size: "larger", "Waiting for game to start" } }
+                            rsx! {
+                                div { align_self: "center", font_size: "larger", "Waiting for game to start" }
+                                if settings.team_mode {
+                                    rsx! { div { display: "flex", gap: "5px", justify_content: "center",
+                                        for (team, team_name) in settings.teams.iter().enumerate() {
+                                            button {
+                                                class: if my_team == Some(team) { "highlighted" } else { "" },
+                                                onclick: move |_| {
+                                                    join_team(lobby_id, player_name, team);
+                                                },
+                                                "Join {team_name}"
+                                            }
+                                        }
+                                    }}
+                                }
+                            }
                         }
                     }
                     if !alert_popup_message.is_empty() {
@@ -120,23 +202,26 @@ pub fn GameView(
                         questions_queue: questions_queue.clone(),
                         questions_queue_active: *questions_queue_active,
                         questions_queue_countdown: *questions_queue_countdown,
+                        elapsed_time: *elapsed_time,
                         settings: settings.clone(),
-                        is_quizmaster: is_quizmaster
+                        is_quizmaster: is_quizmaster,
+                        is_spectator: is_spectator
                     }
                 }
                 div { class: "background-box", flex: "1", min_height: "150px", overflow_y: "auto",
                     div { class: "header-box", "Chat" }
                     div { flex: "1", display: "flex", flex_direction: "column", gap: "3px", overflow_y: "auto",
                         chat_messages.iter().rev().map(|message| {
+                            let team_tag = if message.team.is_some() { "[Team] " } else { "" };
                             rsx! {
-                                div { class: "body-box", "{message.player}: {message.message}" }
+                                div { class: "body-box", "{team_tag}{message.player}: {message.message}" }
                             }
                         })
                     }
                     form {
                         onsubmit: move |form_data| {
                             if let Some(message) = form_data.values.get("message").and_then(|m| m.first()) {
-                                add_chat_message(lobby_id, player_name, message);
+                                add_chat_message(lobby_id, player_name, message, *team_chat.get());
                             }
                         },
                         input {
@@ -146,6 +231,18 @@ pub fn GameView(
                             flex: "1",
                             "data-clear-on-submit": "true"
                         }
+                        if settings.team_mode && my_team.is_some() {
+                            rsx! { label {
+                                "Team "
+                                input {
+                                    r#type: "checkbox",
+                                    checked: "{team_chat}",
+                                    oninput: move |e| {
+                                        team_chat.set(e.value.parse::<bool>().unwrap_or(false));
+                                    }
+                                }
+                            }}
+                        }
                         button { r#type: "submit", "Send" }
                     }
                 }
@@ -153,3 +250,27 @@ pub fn GameView(
         }
     })
 }
+
+#[component]
+fn SeriesScoreboard(cx: Scope, players: Vec<PlayerReduced>, rounds: usize) -> Element {
+    let mut sorted_players = players.iter().filter(|p| !p.spectator).collect::<Vec<_>>();
+    sorted_players.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.name.cmp(&b.name)));
+    cx.render(rsx! {
+        div { class: "table-row",
+            div { class: "header-box", flex: "2", "Player" }
+            for round in 1..=*rounds {
+                div { class: "header-box", flex: "1", "R{round}" }
+            }
+            div { class: "header-box", flex: "1", "Total" }
+        }
+        for player in sorted_players {
+            div { class: "table-row",
+                div { class: "body-box", flex: "2", "{player.name}" }
+                for round in 0..*rounds {
+                    div { class: "body-box", flex: "1", "{player.round_scores.get(round).copied().unwrap_or_default()}" }
+                }
+                div { class: "body-box", flex: "1", "{player.score}" }
+            }
+        }
+    })
+}
@@ -1,9 +1,22 @@
-use crate::backend::{Item, Question};
+use deducers::backend::{
+    items::{item_ambiguity, items_separated, unmask_question},
+    Item, Question,
+};
 use dioxus::prelude::*;
 use std::collections::{HashMap, HashSet};
 
 #[component]
-pub fn ItemDisplay(cx: Scope, player_name: String, is_quizmaster: bool, items: Vec<Item>, questions: Vec<Question>) -> Element {
+pub fn ItemDisplay(
+    cx: Scope,
+    player_name: String,
+    lobby_id: String,
+    is_quizmaster: bool,
+    can_unmask: bool,
+    unmask_cost: usize,
+    items: Vec<Item>,
+    questions: Vec<Question>,
+    questions_per_item: usize,
+) -> Element {
     let questions_by_id: HashMap<usize, &Question> = questions.iter().map(|q| (q.id, q)).collect();
     let mut questions_found = HashSet::new();
 
@@ -13,12 +26,11 @@ pub fn ItemDisplay(cx: Scope, player_name: String, is_quizmaster: bool, items: V
         .filter_map(|(question_id, _)| questions_by_id.get(question_id))
         .filter(|question| questions_found.insert(question.id))
         .map(|question| {
-            let question_text = if question.masked {
-                if &question.player != player_name && !is_quizmaster {
-                    format!("MASKED - {}", question.player)
-                } else {
-                    format!("MASKED {} - {}", question.text, question.player)
-                }
+            let hidden = !question.visible_to(player_name, *is_quizmaster);
+            let question_text = if hidden {
+                format!("MASKED - {}", question.player)
+            } else if question.masked {
+                format!("MASKED {} - {}", question.text, question.player)
             } else {
                 question.text.clone()
             };
@@ -26,25 +38,60 @@ pub fn ItemDisplay(cx: Scope, player_name: String, is_quizmaster: bool, items: V
             let answers = items
                 .iter()
                 .map(|item| item.answers.iter().find(|&(id, _)| id == &question.id).map(|(_, a)| a))
-                .collect();
-            (question.id, question_text, font_style, answers)
+                .collect::<Vec<_>>();
+            let separated = items_separated(&answers.iter().flatten().copied().copied().collect::<Vec<_>>());
+            (question.id, question_text, font_style, hidden, separated.to_string(), answers)
         })
         .collect();
-    active_questions.sort_by(|(id_a, _, _, _), (id_b, _, _, _)| id_a.cmp(id_b));
-    active_questions.resize_with(20, || (0, String::new(), "normal", vec![None; items.len()]));
+    active_questions.sort_by_key(|(id, _, _, _, _, _)| *id);
+    let ambiguity = item_ambiguity(items);
+    let item_hints = items
+        .iter()
+        .filter_map(|item| {
+            let clue = item.clue.as_ref().map(|clue| format!("Clue: {clue}"));
+            let hints = clue
+                .into_iter()
+                .chain(
+                    item.hints
+                        .iter()
+                        .filter(|hint| hint.visible_to(player_name, *is_quizmaster))
+                        .map(|hint| hint.text.clone()),
+                )
+                .collect::<Vec<_>>();
+            (!hints.is_empty()).then(|| (item.id, hints.join("; ")))
+        })
+        .collect::<Vec<_>>();
+    active_questions.resize_with(*questions_per_item, || {
+        (0, String::new(), "normal", false, String::new(), vec![None; items.len()])
+    });
 
     cx.render(rsx! {
         div { class: "table-row",
             div { class: "header-box", flex: "1", "Question" }
+            div { class: "header-box", width: "40px", flex: "unset", text_align: "center", title: "How many items each question separates from the largest group of matching answers", "Split" }
             for item in items {
                 div { class: "header-box", width: if *is_quizmaster { "unset" } else { "20px" }, flex: "unset", text_align: "center",
                     if *is_quizmaster { format!("{}: {}", item.id, item.name) } else { item.id.to_string() }
                 }
             }
         }
-        for (_ , question_string , font_style , answers) in active_questions {
+        for (question_id , question_string , font_style , hidden , separated , answers) in active_questions {
             div { class: "table-row", flex: "1",
-                div { class: "body-box", flex: "1", justify_content: "start", div { font_style: font_style, "{question_string}" } }
+                div { class: "body-box", flex: "1", justify_content: "start",
+                    div { font_style: font_style, "{question_string}" }
+                    if hidden && *can_unmask {
+                        rsx! {
+                            button {
+                                onclick: move |_| {
+                                    unmask_question(lobby_id, player_name, question_id);
+                                },
+                                padding: "2px",
+                                "🔓 {unmask_cost}🪙"
+                            }
+                        }
+                    }
+                }
+                div { class: "body-box", width: "40px", text_align: "center", "{separated}" }
                 for answer in answers {
                     div { class: "body-box", width: "20px", text_align: "center", background_color: answer.map_or("rgb(60, 60, 80)", |answer| answer.to_color()),
                         if answer.is_none() && question_string.is_empty() { "⭐" } else { "" }
@@ -52,5 +99,17 @@ pub fn ItemDisplay(cx: Scope, player_name: String, is_quizmaster: bool, items: V
                 }
             }
         }
+        div { class: "table-row",
+            div { class: "header-box", flex: "1", title: "How many other items have answered every shared question the same way", "Alike Items" }
+            div { class: "header-box", width: "40px", flex: "unset" }
+            for (item , alike) in items.iter().zip(ambiguity) {
+                div { class: "header-box", width: if *is_quizmaster { "unset" } else { "20px" }, flex: "unset", text_align: "center", title: "Item {item.id}",
+                    "{alike}"
+                }
+            }
+        }
+        for (item_id , hints) in item_hints {
+            div { class: "body-box", justify_content: "start", "Item {item_id}: {hints}" }
+        }
     })
 }
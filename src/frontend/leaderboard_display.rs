@@ -1,9 +1,22 @@
-use crate::backend::{kick_player, PlayerReduced};
+use deducers::{
+    backend::{get_current_time, kick_player, transfer_key_player, unban_player, Ban, BanLength, PlayerReduced},
+    MAX_KICK_REASON_LENGTH,
+};
 use dioxus::prelude::*;
 
 #[component]
-pub fn Leaderboard(cx: Scope, player_name: String, lobby_id: String, players: Vec<PlayerReduced>, is_keyplayer: bool) -> Element {
-    let mut sorted_players = players.clone();
+pub fn Leaderboard(
+    cx: Scope,
+    player_name: String,
+    lobby_id: String,
+    players: Vec<PlayerReduced>,
+    bans: Vec<Ban>,
+    teams: Vec<String>,
+    is_keyplayer: bool,
+) -> Element {
+    let kick_target = use_state(cx, || None::<String>);
+    let mut sorted_players = players.iter().filter(|p| !p.spectator).cloned().collect::<Vec<_>>();
+    let spectators = players.iter().filter(|p| p.spectator).map(|p| p.name.clone()).collect::<Vec<_>>();
     sorted_players.sort_by(|a, b| {
         if a.score == b.score {
             a.name.cmp(&b.name)
@@ -11,41 +24,167 @@ pub fn Leaderboard(cx: Scope, player_name: String, lobby_id: String, players: Ve
             b.score.cmp(&a.score)
         }
     });
+    let top_score = sorted_players.first().map_or(0, |p| p.score);
+
+    // Team games list each team under a header with its combined score
+    let groups = if teams.is_empty() {
+        vec![(None, sorted_players)]
+    } else {
+        let mut groups = teams
+            .iter()
+            .enumerate()
+            .map(|(team, team_name)| {
+                let members = sorted_players.iter().filter(|p| p.team == Some(team)).cloned().collect::<Vec<_>>();
+                let team_score = members.iter().map(|p| p.score).sum::<usize>();
+                (Some(format!("{team_name} {team_score}")), members)
+            })
+            .collect::<Vec<_>>();
+        let unassigned = sorted_players.iter().filter(|p| p.team.is_none()).cloned().collect::<Vec<_>>();
+        if !unassigned.is_empty() {
+            groups.push((Some("No team".to_owned()), unassigned));
+        }
+        groups
+    };
 
     cx.render(rsx! {
         div { class: "table-row",
             div { class: "header-box", flex: "2", "Player" }
             div { class: "header-box", flex: "1", "Score" }
         }
-        sorted_players.iter().map(|player| {
-            let row_color = match player {
-                _ if player.quizmaster => "rgb(120, 110, 20)",
-                _ if player.score == sorted_players[0].score => "rgb(80, 80, 60)",
-                _ if player.name == *player_name => "rgb(60, 80, 80)",
-                _ => "rgb(60, 60, 80)",
-            };
-            let (row_player, row_score) = (player.name.clone(), player.score.to_string());
+        groups.iter().map(|(header, group_players)| {
             rsx! {
-                div { class: "table-row",
-                    div { class: "body-box", background_color: row_color, flex: "2", "{row_player}" }
-                    div {
-                        class: "body-box",
-                        background_color: row_color,
-                        flex: "1",
-                        gap: "5px",
-                        if player.quizmaster { "👑" } else { &row_score },
-                        if *is_keyplayer && row_player != *player_name {
-                            rsx! { button {
-                                onclick: move |_| {
-                                    kick_player(lobby_id, player_name, &row_player);
-                                },
-                                padding: "2px",
-                                "💥"
-                            }}
+                if let Some(header) = header {
+                    rsx! { div { class: "table-row", div { class: "header-box", flex: "1", "{header}" } } }
+                }
+                group_players.iter().map(|player| {
+                    let row_color = match player {
+                        _ if player.quizmaster => "rgb(120, 110, 20)",
+                        _ if player.score == top_score => "rgb(80, 80, 60)",
+                        _ if player.name == *player_name => "rgb(60, 80, 80)",
+                        _ => "rgb(60, 60, 80)",
+                    };
+                    let (row_player, row_score) = (player.name.clone(), player.score.to_string());
+                    let away = if player.connected { "" } else { " 💤" };
+                    rsx! {
+                        div { class: "table-row",
+                            div { class: "body-box", background_color: row_color, flex: "2", "{row_player}{away}" }
+                            div {
+                                class: "body-box",
+                                background_color: row_color,
+                                flex: "1",
+                                gap: "5px",
+                                if player.quizmaster { "👑" } else { &row_score },
+                                if *is_keyplayer && row_player != *player_name {
+                                    let transfer_player = row_player.clone();
+                                    rsx! {
+                                        button {
+                                            onclick: move |_| {
+                                                transfer_key_player(lobby_id, player_name, &transfer_player);
+                                            },
+                                            padding: "2px",
+                                            title: "Make host",
+                                            "🔑"
+                                        }
+                                        button {
+                                            onclick: move |_| {
+                                                if kick_target.get().as_ref() == Some(&row_player) {
+                                                    kick_target.set(None);
+                                                } else {
+                                                    kick_target.set(Some(row_player.clone()));
+                                                }
+                                            },
+                                            padding: "2px",
+                                            title: "Kick",
+                                            "💥"
+                                        }
+                                    }
+                                }
+                            }
                         }
                     }
-                }
+                })
             }
         })
+        if !spectators.is_empty() {
+            rsx! {
+                div { class: "header-box", "Spectators" }
+                spectators.iter().map(|spectator| {
+                    let row_player = spectator.clone();
+                    rsx! {
+                        div { class: "table-row",
+                            div { class: "body-box", flex: "2", "👁 {spectator}" }
+                            if *is_keyplayer {
+                                rsx! { div { class: "body-box", flex: "1",
+                                    button {
+                                        onclick: move |_| {
+                                            kick_target.set(Some(row_player.clone()));
+                                        },
+                                        padding: "2px",
+                                        title: "Kick",
+                                        "💥"
+                                    }
+                                }}
+                            }
+                        }
+                    }
+                })
+            }
+        }
+        if let Some(target) = kick_target.get() {
+            rsx! {
+                form {
+                    onsubmit: move |form_data| {
+                        let reason = form_data.values.get("reason").and_then(|m| m.first()).cloned().unwrap_or_default();
+                        let ban_length = match form_data.values.get("ban").and_then(|m| m.first()).map(String::as_str) {
+                            Some("permanent") => BanLength::Permanent,
+                            Some(minutes) => minutes.parse().map_or(BanLength::NoBan, BanLength::Minutes),
+                            None => BanLength::NoBan,
+                        };
+                        kick_player(lobby_id, player_name, target, reason.trim(), ban_length);
+                        kick_target.set(None);
+                    },
+                    input {
+                        r#type: "text",
+                        placeholder: "Reason for kicking {target}",
+                        name: "reason",
+                        flex: "1",
+                        maxlength: MAX_KICK_REASON_LENGTH as i64
+                    }
+                    select { name: "ban",
+                        option { value: "none", "{BanLength::NoBan}" }
+                        option { value: "5", "{BanLength::Minutes(5)}" }
+                        option { value: "60", "{BanLength::Minutes(60)}" }
+                        option { value: "permanent", "{BanLength::Permanent}" }
+                    }
+                    button { r#type: "submit", background_color: "rgb(100, 20, 20)", "Kick" }
+                }
+            }
+        }
+        if *is_keyplayer && !bans.is_empty() {
+            rsx! {
+                div { class: "header-box", "Banned" }
+                bans.iter().map(|ban| {
+                    let banned_player = ban.player_name.clone();
+                    let duration = ban.expires_at.map_or_else(
+                        || "permanently".to_owned(),
+                        |expires_at| format!("{}m left", ((expires_at - get_current_time()) / 60.0).ceil().max(0.0)),
+                    );
+                    rsx! {
+                        div { class: "table-row",
+                            div { class: "body-box", flex: "2", "{ban.player_name} {duration}" }
+                            div { class: "body-box", flex: "1",
+                                button {
+                                    onclick: move |_| {
+                                        unban_player(lobby_id, player_name, &banned_player);
+                                    },
+                                    padding: "2px",
+                                    "Unban"
+                                }
+                            }
+                        }
+                    }
+                })
+            }
+        }
     })
 }
@@ -1,13 +1,15 @@
-use crate::{
+use deducers::{
     backend::{
         alert_popup,
-        items::player_guess_item,
+        items::{buy_hint, player_guess_item},
         question_queue::{convert_score, submit_question},
-        Item, LobbySettings,
+        HintKind, Item, LobbySettings,
     },
     ITEM_NAME_PATTERN, MAX_ITEM_NAME_LENGTH, MAX_QUESTION_LENGTH, QUESTION_PATTERN,
 };
 use dioxus::prelude::*;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
 
 #[component]
 pub fn Management(
@@ -34,11 +36,7 @@ pub fn Management(
                             = submit_question(&lobby_id, &player_name, question.clone(), masked)
                                 .await
                         {
-                            alert_popup(
-                                &lobby_id,
-                                &player_name,
-                                &format!("Question rejected {error}"),
-                            );
+                            alert_popup(&lobby_id, &player_name, error);
                         }
                     });
                 }
@@ -105,5 +103,33 @@ pub fn Management(
             }
             button { r#type: "submit", "Submit Guess {settings.guess_item_cost}🪙" }
         }
+        form {
+            onsubmit: move |form_data| {
+                if let (Some(kind), Some(item_choice))
+                    = (
+                        form_data.values.get("kind").and_then(|m| m.first()).and_then(|k| HintKind::from_str(k).ok()),
+                        form_data
+                            .values
+                            .get("key")
+                            .and_then(|m| m.first())
+                            .and_then(|k| k.parse().ok()),
+                    ) {
+                    buy_hint(lobby_id, player_name, item_choice, kind);
+                }
+            },
+            select { name: "kind", flex: "1",
+                for kind in HintKind::iter() {
+                    option { "{kind}" }
+                }
+            }
+            select { name: "key",
+                items.iter().map(|item| {
+                    rsx! {
+                        option { "{item.id}" }
+                    }
+                })
+            }
+            button { r#type: "submit", "Buy Hint {settings.hint_cost}🪙" }
+        }
     })
 }
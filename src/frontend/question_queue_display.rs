@@ -1,4 +1,11 @@
-use crate::backend::{question_queue::vote_question, LobbySettings, QueuedQuestion};
+use deducers::{
+    backend::{
+        alert_popup,
+        question_queue::{bid_question, edit_question, unvote_question, vote_question, withdraw_question},
+        LobbySettings, QueueMode, QueuedQuestion,
+    },
+    MAX_QUESTION_LENGTH, QUESTION_PATTERN,
+};
 use dioxus::prelude::*;
 
 #[component]
@@ -7,15 +14,21 @@ pub fn QuestionQueueDisplay(
     player_name: String,
     lobby_id: String,
     is_quizmaster: bool,
+    is_spectator: bool,
     questions_queue: Vec<QueuedQuestion>,
     questions_queue_active: bool,
     questions_queue_countdown: usize,
+    elapsed_time: usize,
     settings: LobbySettings,
 ) -> Element {
+    let editing = use_state(cx, || None::<usize>);
+    let auction = settings.queue_mode == QueueMode::Auction;
     cx.render(rsx! {
         div { align_self: "center",
             if *questions_queue_active {
                 format!("Top Question Submitted in {questions_queue_countdown} Seconds")
+            } else if auction {
+                format!("Top Question Submitted After Bids Totalling {}🪙", settings.min_total_bid)
             } else {
                 format!("Top Question Submitted After {} Votes", settings.question_min_votes)
             }
@@ -23,10 +36,11 @@ pub fn QuestionQueueDisplay(
         div { class: "table-row",
             div { class: "header-box", flex: "1", "Player" }
             div { class: "header-box", flex: "3", "Question" }
-            div { class: "header-box", flex: "1", "Votes" }
+            div { class: "header-box", flex: "1", if auction { "Your Bid" } else { "Votes" } }
         }
         questions_queue.iter().map(|question| {
-            let row_class = format!("body-box{}", if question.player == *player_name { " self" } else { "" });
+            let is_own = question.player == *player_name;
+            let row_class = if is_own { "body-box self" } else { "body-box" };
             let question_text = if question.masked {
                 if question.player != *player_name && !is_quizmaster {
                     "MASKED".to_owned()
@@ -36,20 +50,112 @@ pub fn QuestionQueueDisplay(
             } else {
                 question.question.clone()
             };
+            let expires_in = (settings.question_expiry_seconds > 0)
+                .then(|| (question.queued_at.round() as usize).saturating_add(settings.question_expiry_seconds).saturating_sub(*elapsed_time));
+            let has_voted = question.voters.contains(player_name);
+            let my_bid = question.bids.get(player_name).copied().unwrap_or_default();
+            let question_id = question.id;
             rsx! {
                 div { class: "table-row",
                     div { class: "{row_class}", flex: "1", "{question.player}" }
-                    div { class: "{row_class}", flex: "3", "{question_text}" }
-                    div { class: "{row_class}", flex: "1",
-                        "{question.voters.len()}"
-                        if !is_quizmaster {
+                    div { class: "{row_class}", flex: "3",
+                        if *editing.get() == Some(question_id) {
                             rsx! {
-                                button {
-                                    onclick: move |_| {
-                                        vote_question(lobby_id, player_name, &question.question);
+                                form {
+                                    onsubmit: move |form_data| {
+                                        if let Some(new_question) = form_data.values.get("question").and_then(|m| m.first()) {
+                                            let new_question = new_question.to_owned();
+                                            let (lobby_id, player_name) = (lobby_id.to_owned(), player_name.to_owned());
+                                            editing.set(None);
+                                            cx.spawn(async move {
+                                                if let Err(error) = edit_question(&lobby_id, &player_name, question_id, new_question).await {
+                                                    alert_popup(&lobby_id, &player_name, error);
+                                                }
+                                            });
+                                        }
                                     },
-                                    padding: "2px",
-                                    "🪙"
+                                    input {
+                                        r#type: "text",
+                                        name: "question",
+                                        flex: "1",
+                                        value: "{question.question}",
+                                        pattern: QUESTION_PATTERN,
+                                        maxlength: MAX_QUESTION_LENGTH as i64
+                                    }
+                                    button { r#type: "submit", padding: "2px", "Save" }
+                                }
+                            }
+                        } else {
+                            rsx! {
+                                "{question_text}"
+                                if let Some(expires_in) = expires_in {
+                                    rsx! { span { font_size: "smaller", " ⏳{expires_in}s" } }
+                                }
+                                if is_own {
+                                    rsx! {
+                                        button {
+                                            onclick: move |_| {
+                                                editing.set(Some(question_id));
+                                            },
+                                            padding: "2px",
+                                            "✏️"
+                                        }
+                                        button {
+                                            onclick: move |_| {
+                                                withdraw_question(lobby_id, player_name, question_id);
+                                            },
+                                            padding: "2px",
+                                            "✖"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if auction {
+                        rsx! {
+                            div { class: "{row_class}", flex: "1",
+                                "{my_bid}🪙"
+                                if !is_quizmaster && !is_spectator {
+                                    rsx! {
+                                        form {
+                                            onsubmit: move |form_data| {
+                                                if let Some(amount) = form_data.values.get("amount").and_then(|m| m.first()) {
+                                                    bid_question(lobby_id, player_name, question_id, amount.parse::<usize>().unwrap_or_default());
+                                                }
+                                            },
+                                            input { r#type: "number", name: "amount", min: "1", value: "1", max_width: "40px" }
+                                            button { r#type: "submit", padding: "2px", "Bid" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    } else {
+                        rsx! {
+                            div { class: "{row_class}", flex: "1",
+                                "{question.voters.len()}"
+                                if !is_quizmaster && !is_spectator {
+                                    rsx! {
+                                        button {
+                                            onclick: move |_| {
+                                                vote_question(lobby_id, player_name, question_id);
+                                            },
+                                            padding: "2px",
+                                            "🪙"
+                                        }
+                                    }
+                                }
+                                if has_voted {
+                                    rsx! {
+                                        button {
+                                            onclick: move |_| {
+                                                unvote_question(lobby_id, player_name, question_id);
+                                            },
+                                            padding: "2px",
+                                            "↩"
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -1,4 +1,4 @@
-use crate::backend::{
+use deducers::backend::{
     items::{quizmaster_change_answer, quizmaster_reject, quizmaster_submit},
     Answer, Item, QueuedQuestion,
 };
@@ -25,14 +25,14 @@ pub fn QuizmasterDisplay(
                     div { class: "body-box", flex: "1", "{question.question}" }
                     button {
                         onclick: move |_| {
-                            quizmaster_submit(lobby_id, player_name, &question.question);
+                            quizmaster_submit(lobby_id, player_name, question.id);
                         },
                         background_color: "rgb(20, 100, 20)",
                         "Submit"
                     }
                     button {
                         onclick: move |_| {
-                            quizmaster_reject(lobby_id, player_name, &question.question);
+                            quizmaster_reject(lobby_id, player_name, question.id);
                         },
                         background_color: "rgb(100, 20, 20)",
                         "Reject"
@@ -58,7 +58,7 @@ pub fn QuizmasterDisplay(
                                             border: "1px solid white",
                                             background_color: answer.to_color(),
                                             onclick: move |_| {
-                                                quizmaster_change_answer(lobby_id, player_name, &question.question, item.id, answer);
+                                                quizmaster_change_answer(lobby_id, player_name, question.id, item.id, answer);
                                             }
                                         }
                                     }
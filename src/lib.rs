@@ -0,0 +1,38 @@
+#![warn(clippy::nursery, clippy::pedantic)]
+#![allow(clippy::too_many_lines, clippy::derive_partial_eq_without_eq)]
+#![allow(clippy::significant_drop_tightening, clippy::cast_possible_wrap)]
+#![allow(clippy::cast_sign_loss, clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc, clippy::must_use_candidate)]
+
+// The game engine, lobbies, players, questions, scoring and AI hooks, usable without the web frontend
+pub mod backend;
+
+pub const IDLE_KICK_TIME: f64 = 10.0;
+pub const RECONNECT_GRACE_TIME: f64 = 60.0;
+
+pub const SNAPSHOT_FILE: &str = "lobbies_snapshot.json";
+
+pub const MAX_QUESTION_LENGTH: usize = 70;
+pub const QUESTION_PATTERN: &str = "^[a-zA-Z0-9 ?]+$"; // Alphanumeric and spaces and question mark only
+
+pub const MAX_ITEM_NAME_LENGTH: usize = 30;
+pub const ITEM_NAME_PATTERN: &str = "^[a-zA-Z]+$"; // Alphabetic only
+
+pub const MAX_LOBBY_ID_LENGTH: usize = 20;
+pub const LOBBY_ID_PATTERN: &str = "^[a-zA-Z0-9]+$"; // Alphanumeric only
+pub const MAX_PLAYER_NAME_LENGTH: usize = 20;
+pub const PLAYER_NAME_PATTERN: &str = "^[a-zA-Z0-9 ]+$"; // Alphanumeric and spaces only
+pub const MAX_LOBBY_PASSWORD_LENGTH: usize = 20;
+pub const LOBBY_PASSWORD_PATTERN: &str = "^[a-zA-Z0-9]*$"; // Alphanumeric only, empty for invite links only
+
+pub const MAX_LOBBY_ITEMS: usize = 20;
+pub const MAX_QUESTIONS_PER_ITEM: usize = 50;
+pub const MAX_LOBBY_PLAYERS: usize = 50;
+pub const MAX_KICK_REASON_LENGTH: usize = 50;
+pub const MAX_FINISHED_GAMES: usize = 50;
+pub const MAX_SERIES_ROUNDS: usize = 10;
+pub const MAX_TEAMS: usize = 4;
+pub const MAX_TEAM_NAME_LENGTH: usize = 15;
+
+pub const MAX_CHAT_LENGTH: usize = 100;
+pub const MAX_CHAT_MESSAGES: usize = 20;
@@ -2,13 +2,15 @@
 #![allow(clippy::too_many_lines, clippy::derive_partial_eq_without_eq)]
 #![allow(clippy::significant_drop_tightening, clippy::cast_possible_wrap)]
 #![allow(clippy::cast_sign_loss, clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+#![allow(mismatched_lifetime_syntaxes)]
 use crate::frontend::app;
 use axum::{extract::ws::WebSocketUpgrade, response::Html, routing::get, Router};
+use deducers::backend;
 use std::{env, net::SocketAddr, time::Duration};
-use tokio::time::sleep;
+use tokio::{signal, time::sleep};
 use tower_http::services::ServeDir;
 
-mod backend;
+mod api;
 mod frontend;
 
 pub const SERVER_PORT: u16 = 3013;
@@ -16,23 +18,7 @@ pub const SERVER_PORT: u16 = 3013;
 pub const CLIENT_UPDATE_INTERVAL: f64 = 0.5;
 pub const SERVER_UPDATE_INTERVAL: f64 = 0.5;
 
-pub const IDLE_KICK_TIME: f64 = 10.0;
-
-pub const MAX_QUESTION_LENGTH: usize = 70;
-pub const QUESTION_PATTERN: &str = "^[a-zA-Z0-9 ?]+$"; // Alphanumeric and spaces and question mark only
-
-pub const MAX_ITEM_NAME_LENGTH: usize = 30;
-pub const ITEM_NAME_PATTERN: &str = "^[a-zA-Z]+$"; // Alphabetic only
-
-pub const MAX_LOBBY_ID_LENGTH: usize = 20;
-pub const LOBBY_ID_PATTERN: &str = "^[a-zA-Z0-9]+$"; // Alphanumeric only
-pub const MAX_PLAYER_NAME_LENGTH: usize = 20;
-pub const PLAYER_NAME_PATTERN: &str = "^[a-zA-Z0-9 ]+$"; // Alphanumeric and spaces only
-
-pub const MAX_LOBBY_ITEMS: usize = 20;
-
-pub const MAX_CHAT_LENGTH: usize = 100;
-pub const MAX_CHAT_MESSAGES: usize = 20;
+pub const SNAPSHOT_INTERVAL: f64 = 30.0;
 
 #[tokio::main]
 async fn main() {
@@ -41,6 +27,13 @@ async fn main() {
     let server_ip = env::var("SERVER_IP").unwrap_or_else(|_| "127.0.0.1".to_owned());
     let server_address = format!("{server_ip}:{SERVER_PORT}");
 
+    // Restore lobbies saved before the last shutdown
+    match backend::snapshot::load_snapshot() {
+        Ok(0) => {}
+        Ok(lobbies_count) => println!("Restored {lobbies_count} lobbies from snapshot"),
+        Err(e) => println!("Failed to restore snapshot {e}"),
+    }
+
     // Include the contents of CSS and JS files
     let css_content = String::from_utf8_lossy(
         &rsass::compile_scss(include_bytes!("style.scss"), rsass::output::Format::default()).expect("Failed to compile SCSS"),
@@ -81,6 +74,10 @@ async fn main() {
                 })
             }),
         )
+        .route("/api/v1/ws", get(api::game_socket))
+        .route("/api/v1/lobbies", get(api::list_lobbies))
+        .route("/api/v1/lobbies/:lobby_id/scoreboard", get(api::lobby_scoreboard))
+        .route("/api/v1/lobbies/:lobby_id/results", get(api::lobby_results))
         .nest_service("/assets/", ServeDir::new("assets"));
 
     println!("Listening on http://{addr}");
@@ -92,8 +89,43 @@ async fn main() {
         }
     });
 
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs_f64(SNAPSHOT_INTERVAL)).await;
+            if let Err(e) = backend::snapshot::save_snapshot() {
+                println!("Failed to save snapshot {e}");
+            }
+        }
+    });
+
     axum::Server::bind(&addr.to_string().parse().unwrap())
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    if let Err(e) = backend::snapshot::save_snapshot() {
+        println!("Failed to save snapshot {e}");
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("Failed to listen for ctrl-c");
+    };
+    // Only unix has a terminate signal, elsewhere ctrl-c is the only way to shut down cleanly
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for terminate signal")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+    println!("Shutting down, saving lobbies");
 }